@@ -0,0 +1,69 @@
+//! Compares the interpreted and compiled rule sets against the hand-written
+//! Rust translation of the same DTL, run with `cargo bench`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use dtl::{compile::compile, dtl::*, entity::EntityValue, rules::RuleSet};
+use serde_json::json;
+
+const ITERATIONS: u32 = 100_000;
+
+fn hello_world2(source: &EntityValue) -> Vec<EntityValue> {
+    let mut target = Target::new();
+    target.add(
+        "hello",
+        concat(&list_literal(&[
+            string_literal("wor"),
+            number_literal(1),
+            concat(&list_literal(&[
+                string_literal("l"),
+                lower(path(
+                    list_literal(&[string_literal("x"), string_literal("y")]),
+                    source,
+                )),
+                null_literal(),
+            ])),
+        ])),
+    );
+    target.output()
+}
+
+fn measure(name: &str, function: impl Fn(&EntityValue) -> Vec<EntityValue>, source: &EntityValue) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(function(black_box(source)));
+    }
+    let elapsed: Duration = start.elapsed();
+    println!(
+        "{:<12} {:>8} ns/entity",
+        name,
+        elapsed.as_nanos() / u128::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    let rules = RuleSet::parse(&json!({
+        "default": [
+            ["add", "hello",
+                ["concat", "wor", 1,
+                    ["concat", "l", ["lower", "_S.x.y"], null]
+                ]
+            ]
+        ]
+    }))
+    .unwrap();
+    let compiled = compile(&rules);
+    let source: EntityValue = json!({
+        "x": { "y": "D" },
+        "padding": ["a", "b", "c", "d", "e", "f", "g", "h"]
+    })
+    .into();
+
+    assert_eq!(hello_world2(&source), compiled.execute(&source));
+    measure("hand-written", hello_world2, &source);
+    measure("interpreted", |s| rules.execute(s), &source);
+    measure("compiled", |s| compiled.execute(s), &source);
+}
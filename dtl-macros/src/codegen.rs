@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use dtl::{
+    ast::{Expression, Function, Property, Transform, Variable},
+    entity::EntityValue,
+    rules::{RuleSet, DEFAULT_RULE},
+};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+
+/// Generates a block evaluating to a `fn(&EntityValue) -> Vec<EntityValue>`
+/// that runs the default rule, with one Rust function per named rule.
+pub(crate) fn rule_set(rules: &RuleSet) -> TokenStream {
+    let idents: HashMap<&str, Ident> = rules
+        .iter()
+        .enumerate()
+        .map(|(index, (name, _))| (name, format_ident!("rule_{}", index)))
+        .collect();
+    let functions = rules.iter().map(|(name, rule)| {
+        let ident = &idents[name];
+        let transforms = rule.iter().map(|t| transform(t, &idents));
+        quote! {
+            #[allow(unused_variables, unused_mut)]
+            fn #ident(
+                source: &::dtl::entity::EntityValue,
+                parent: ::std::option::Option<&::dtl::eval::Context>,
+            ) -> ::std::vec::Vec<::dtl::entity::EntityValue> {
+                let context = ::dtl::eval::Context::new(source);
+                let context = &match parent {
+                    ::std::option::Option::Some(parent) => context.with_parent(parent),
+                    ::std::option::Option::None => context,
+                };
+                let mut target = ::dtl::dtl::Target::new();
+                #(#transforms)*
+                target.output()
+            }
+        }
+    });
+    let default = &idents[DEFAULT_RULE];
+    quote! {
+        {
+            #(#functions)*
+            fn transform(
+                source: &::dtl::entity::EntityValue,
+            ) -> ::std::vec::Vec<::dtl::entity::EntityValue> {
+                #default(source, ::std::option::Option::None)
+            }
+            transform as fn(&::dtl::entity::EntityValue) -> ::std::vec::Vec<::dtl::entity::EntityValue>
+        }
+    }
+}
+
+fn transform(transform: &Transform, idents: &HashMap<&str, Ident>) -> TokenStream {
+    // expressions see the target as it is before the transform changes it
+    let with_target = |expression: &Expression| {
+        let expression = self::expression(expression, idents);
+        quote! {
+            {
+                let context = &context.with_target(target.entity());
+                #expression
+            }
+        }
+    };
+    match transform {
+        Transform::Add(property, expression) => {
+            set(quote!(add), property, with_target(expression), with_target)
+        }
+        Transform::Default(property, expression) => set(
+            quote!(set_default),
+            property,
+            with_target(expression),
+            with_target,
+        ),
+        Transform::Rename(from, to) => quote!(target.rename(#from, #to);),
+        Transform::Create(expression, None) => update(quote!(create), with_target(expression)),
+        Transform::Create(expression, Some(id)) => {
+            let value = self::expression(expression, idents);
+            let id = self::expression(id, idents);
+            update(
+                quote!(create),
+                quote! {
+                    {
+                        let context = &context.with_target(target.entity());
+                        ::dtl::dtl::set_ids(#value, |entity| {
+                            let context = &context.with_current(entity);
+                            #id
+                        })
+                    }
+                },
+            )
+        }
+        Transform::Merge(expression) => update(quote!(merge), with_target(expression)),
+        Transform::MergeUnion(expression) => update(quote!(merge_union), with_target(expression)),
+        Transform::Copy(include, exclude) => quote! {
+            target.copy(
+                &[#(#include),*] as &[&str],
+                &[#(#exclude),*] as &[&str],
+                context.source,
+            );
+        },
+        Transform::Remove(include, exclude) => quote! {
+            target.remove(&[#(#include),*] as &[&str], &[#(#exclude),*] as &[&str]);
+        },
+        Transform::Filter(condition) => conditional(quote!(filter), condition, with_target),
+        Transform::Discard(condition) => conditional(quote!(discard), condition, with_target),
+        Transform::If(condition, then, otherwise) => {
+            let condition = with_target(condition);
+            let then = then.iter().map(|t| self::transform(t, idents));
+            let otherwise = otherwise.iter().map(|t| self::transform(t, idents));
+            quote! {
+                if ::dtl::dtl::is_true(&#condition) {
+                    #(#then)*
+                } else {
+                    #(#otherwise)*
+                }
+            }
+        }
+        Transform::Case(branches) => {
+            let branches = branches.iter().map(|(condition, rule)| {
+                let condition = with_target(condition);
+                let rule = rule.iter().map(|t| self::transform(t, idents));
+                quote! {
+                    if ::dtl::dtl::is_true(&#condition) {
+                        #(#rule)*
+                    }
+                }
+            });
+            quote!(#(#branches)else*)
+        }
+        Transform::Comment => quote!(),
+        Transform::Trace(expression) => {
+            let value = with_target(expression);
+            quote!(#value;)
+        }
+    }
+}
+
+fn conditional(
+    action: TokenStream,
+    condition: &Option<Expression>,
+    with_target: impl Fn(&Expression) -> TokenStream,
+) -> TokenStream {
+    match condition {
+        Some(condition) => {
+            let condition = with_target(condition);
+            quote! {
+                if ::dtl::dtl::is_true(&#condition) {
+                    target.#action();
+                }
+            }
+        }
+        None => quote!(target.#action();),
+    }
+}
+
+fn update(updater: TokenStream, value: TokenStream) -> TokenStream {
+    quote! {
+        let value = #value;
+        target.#updater(value);
+    }
+}
+
+fn set(
+    setter: TokenStream,
+    property: &Property,
+    value: TokenStream,
+    with_target: impl Fn(&Expression) -> TokenStream,
+) -> TokenStream {
+    match property {
+        Property::Name(name) => quote! {
+            let value = #value;
+            target.#setter(#name, value);
+        },
+        Property::Expression(name) => {
+            let name = with_target(name);
+            quote! {
+                let name = #name;
+                let value = #value;
+                if let ::dtl::entity::EntityValue::String(name) = name {
+                    target.#setter(&name, value);
+                }
+            }
+        }
+    }
+}
+
+fn expression(expression: &Expression, idents: &HashMap<&str, Ident>) -> TokenStream {
+    match expression {
+        Expression::Literal(value) => literal(value),
+        Expression::Variable(Variable::Source) => quote!(context.source.clone()),
+        Expression::Variable(variable) => {
+            let variable = variable_ident(*variable);
+            quote!(context.#variable.cloned().unwrap_or(::dtl::entity::EntityValue::Null))
+        }
+        Expression::Parent(value) => {
+            let value = self::expression(value, idents);
+            quote! {
+                match context.parent {
+                    ::std::option::Option::Some(context) => #value,
+                    ::std::option::Option::None => ::dtl::entity::EntityValue::Null,
+                }
+            }
+        }
+        Expression::Root(value) => {
+            let value = self::expression(value, idents);
+            quote! {
+                {
+                    let context = context.root();
+                    #value
+                }
+            }
+        }
+        Expression::Path(segments, value) => {
+            let segments = quote!(&[#(#segments),*] as &[&str]);
+            match value.as_ref() {
+                // walk the variable in place instead of cloning all of it first
+                Expression::Variable(Variable::Source) => {
+                    quote!(::dtl::dtl::path_segments(#segments, context.source).clone())
+                }
+                Expression::Variable(variable) => {
+                    let variable = variable_ident(*variable);
+                    quote! {
+                        context
+                            .#variable
+                            .map_or(&::dtl::entity::EntityValue::Null, |value| {
+                                ::dtl::dtl::path_segments(#segments, value)
+                            })
+                            .clone()
+                    }
+                }
+                value => {
+                    let value = self::expression(value, idents);
+                    quote!(::dtl::dtl::path_segments(#segments, &#value).clone())
+                }
+            }
+        }
+        Expression::Apply(name, value) => {
+            let value = self::expression(value, idents);
+            let rule = &idents[name.as_str()];
+            quote! {
+                ::dtl::dtl::apply(
+                    |item| #rule(item, ::std::option::Option::Some(context)),
+                    &#value,
+                )
+            }
+        }
+        Expression::Call(Function::Map, args) => {
+            let [function, items] = args.as_slice() else {
+                return quote!(::dtl::entity::EntityValue::Null);
+            };
+            let function = self::expression(function, idents);
+            let items = self::expression(items, idents);
+            quote! {
+                ::dtl::dtl::map(
+                    |item| {
+                        let context = &context.with_current(item);
+                        #function
+                    },
+                    &#items,
+                )
+            }
+        }
+        Expression::Call(function, args) if function.is_coercion() && args.len() == 2 => {
+            let function = Ident::new(&format!("{:?}", function), Span::call_site());
+            let default = self::expression(&args[0], idents);
+            let source = self::expression(&args[1], idents);
+            quote! {
+                ::dtl::eval::coerce(
+                    ::dtl::ast::Function::#function,
+                    ::std::option::Option::Some(&|value: &::dtl::entity::EntityValue| {
+                        let context = &context.with_current(value);
+                        #default
+                    }),
+                    &#source,
+                )
+            }
+        }
+        Expression::Call(function, args) => {
+            let function = Ident::new(&format!("{:?}", function), Span::call_site());
+            match args.as_slice() {
+                // the parser already checked the pattern, it is compiled the first time it's used
+                [Expression::Pattern(pattern), args @ ..] => {
+                    let pattern = pattern.as_str();
+                    let args = args.iter().map(|arg| self::expression(arg, idents));
+                    quote! {
+                        {
+                            static PATTERN: ::std::sync::OnceLock<::dtl::ast::Pattern> =
+                                ::std::sync::OnceLock::new();
+                            let pattern = PATTERN
+                                .get_or_init(|| ::dtl::ast::Pattern::new(#pattern).unwrap());
+                            ::dtl::eval::call_pattern(
+                                ::dtl::ast::Function::#function,
+                                pattern,
+                                &[#(#args),*],
+                            )
+                        }
+                    }
+                }
+                args => {
+                    let args = args.iter().map(|arg| self::expression(arg, idents));
+                    quote!(::dtl::eval::call(::dtl::ast::Function::#function, &[#(#args),*]))
+                }
+            }
+        }
+        Expression::Pattern(pattern) => literal(&EntityValue::String(pattern.as_str().to_owned())),
+        // generated functions start without a trace, so this only passes the value on
+        Expression::Trace(point, value) => {
+            let (pointer, expression) = (&point.pointer, &point.expression);
+            let value = self::expression(value, idents);
+            quote!(::dtl::eval::trace(#pointer, #expression, #value, context))
+        }
+    }
+}
+
+fn variable_ident(variable: Variable) -> Ident {
+    Ident::new(variable.name(), Span::call_site())
+}
+
+fn literal(value: &EntityValue) -> TokenStream {
+    match value {
+        EntityValue::Null => quote!(::dtl::entity::EntityValue::Null),
+        EntityValue::Bool(b) => quote!(::dtl::entity::EntityValue::Bool(#b)),
+        EntityValue::String(s) => {
+            quote!(::dtl::entity::EntityValue::String(::std::string::String::from(#s)))
+        }
+        // numbers and transit values are written as the json they were read from
+        other => {
+            let json = serde_json::to_string(other).unwrap();
+            quote! {
+                <::dtl::entity::EntityValue as ::std::str::FromStr>::from_str(#json).unwrap()
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+use serde_json::{Map, Value};
+
+/// The span of every JSON node in the macro input, by JSON pointer.
+#[derive(Default)]
+pub(crate) struct Spans(HashMap<String, Span>);
+
+impl Spans {
+    /// Finds the span of the node at the pointer, or of its closest ancestor.
+    pub(crate) fn find(&self, pointer: &str) -> Span {
+        let mut pointer = pointer;
+        loop {
+            if let Some(span) = self.0.get(pointer) {
+                return *span;
+            }
+            match pointer.rfind('/') {
+                Some(index) => pointer = &pointer[..index],
+                None => return Span::call_site(),
+            }
+        }
+    }
+}
+
+pub(crate) type Error = (Span, String);
+
+/// Reads the JSON written as Rust tokens, e.g. `[["add", "a", -1]]`.
+pub(crate) fn parse(tokens: TokenStream, spans: &mut Spans) -> Result<Value, Error> {
+    let mut tokens = tokens.into_iter().peekable();
+    let value = value(&mut tokens, "", spans)?;
+    match tokens.next() {
+        Some(token) => Err((token.span(), "unexpected token after JSON value".into())),
+        None => Ok(value),
+    }
+}
+
+type Tokens = std::iter::Peekable<proc_macro2::token_stream::IntoIter>;
+
+fn value(tokens: &mut Tokens, pointer: &str, spans: &mut Spans) -> Result<Value, Error> {
+    let Some(token) = tokens.next() else {
+        return Err((Span::call_site(), "expected a JSON value".into()));
+    };
+    spans.0.insert(pointer.to_owned(), token.span());
+    match token {
+        TokenTree::Group(group) => match group.delimiter() {
+            Delimiter::Bracket => {
+                let mut items = Vec::new();
+                let mut inner = group.stream().into_iter().peekable();
+                while inner.peek().is_some() {
+                    let item_pointer = format!("{}/{}", pointer, items.len());
+                    items.push(value(&mut inner, &item_pointer, spans)?);
+                    comma(&mut inner)?;
+                }
+                Ok(Value::Array(items))
+            }
+            Delimiter::Brace => {
+                let mut map = Map::new();
+                let mut inner = group.stream().into_iter().peekable();
+                while inner.peek().is_some() {
+                    let key = match inner.next() {
+                        Some(TokenTree::Literal(literal)) => match string(&literal.to_string()) {
+                            Some(key) => key,
+                            None => return Err((literal.span(), "expected a string key".into())),
+                        },
+                        other => return Err((span(other), "expected a string key".into())),
+                    };
+                    match inner.next() {
+                        Some(TokenTree::Punct(punct)) if punct.as_char() == ':' => {}
+                        other => return Err((span(other), "expected ':'".into())),
+                    }
+                    let key_pointer =
+                        format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                    let item = value(&mut inner, &key_pointer, spans)?;
+                    map.insert(key, item);
+                    comma(&mut inner)?;
+                }
+                Ok(Value::Object(map))
+            }
+            // groups without delimiters come from macro_rules! expansions
+            Delimiter::None => value(&mut group.stream().into_iter().peekable(), pointer, spans),
+            Delimiter::Parenthesis => Err((group.span(), "unexpected parenthesis".into())),
+        },
+        TokenTree::Ident(ident) => match ident.to_string().as_str() {
+            "null" => Ok(Value::Null),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err((ident.span(), "expected a JSON value".into())),
+        },
+        TokenTree::Punct(punct) if punct.as_char() == '-' => match tokens.next() {
+            Some(TokenTree::Literal(literal)) => number(&format!("-{}", literal), literal.span()),
+            other => Err((span(other), "expected a number".into())),
+        },
+        TokenTree::Punct(punct) => Err((punct.span(), "expected a JSON value".into())),
+        TokenTree::Literal(literal) => {
+            let text = literal.to_string();
+            match string(&text) {
+                Some(s) => Ok(Value::String(s)),
+                None => number(&text, literal.span()),
+            }
+        }
+    }
+}
+
+fn comma(tokens: &mut Tokens) -> Result<(), Error> {
+    match tokens.next() {
+        None => Ok(()),
+        Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => Ok(()),
+        Some(token) => Err((token.span(), "expected ','".into())),
+    }
+}
+
+fn string(literal: &str) -> Option<String> {
+    if literal.starts_with('"') {
+        serde_json::from_str(literal).ok()
+    } else {
+        None
+    }
+}
+
+fn number(literal: &str, span: Span) -> Result<Value, Error> {
+    match serde_json::from_str(literal) {
+        Ok(number @ Value::Number(_)) => Ok(number),
+        _ => Err((span, format!("invalid JSON value {}", literal))),
+    }
+}
+
+fn span(token: Option<TokenTree>) -> Span {
+    token.map_or(Span::call_site(), |t| t.span())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let mut spans = Spans::default();
+        let value = parse(
+            quote!({"default": [["add", "a", -1.5], ["add", "b", [true, false, null]]]}),
+            &mut spans,
+        )
+        .unwrap();
+        assert_eq!(
+            json!({"default": [["add", "a", -1.5], ["add", "b", [true, false, null]]]}),
+            value
+        );
+        assert!(spans.0.contains_key("/default/1/2/2"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut spans = Spans::default();
+        assert!(parse(quote!([["add" "a"]]), &mut spans).is_err());
+        assert!(parse(quote!([["add", a]]), &mut spans).is_err());
+        assert!(parse(quote!({a: 1}), &mut spans).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Translates DTL into Rust at compile time, the same way the hand-written
+//! functions in `dtl`'s `lib.rs` do.
+
+mod codegen;
+mod json;
+
+use std::path::PathBuf;
+
+use dtl::rules::{RuleSet, DEFAULT_RULE};
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned};
+use serde_json::{Map, Value};
+
+/// Compiles an inline DTL rule set, or a single rule, into a
+/// `fn(&EntityValue) -> Vec<EntityValue>`.
+///
+/// ```ignore
+/// let transform = dtl!([["add", "hello", "world"]]);
+/// ```
+#[proc_macro]
+pub fn dtl(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut spans = json::Spans::default();
+    match json::parse(input.into(), &mut spans) {
+        Ok(document) => expand(
+            document,
+            |pointer| spans.find(pointer),
+            |_, message| message.into(),
+        ),
+        Err((span, message)) => compile_error(span, &message),
+    }
+    .into()
+}
+
+/// Compiles a DTL rule set read from a JSON file, relative to the crate root,
+/// into a `fn(&EntityValue) -> Vec<EntityValue>`.
+///
+/// ```ignore
+/// let transform = include_dtl!("rules.json");
+/// ```
+#[proc_macro]
+pub fn include_dtl(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let (path, span) = match path_literal(input.into()) {
+        Ok(path) => path,
+        Err((span, message)) => return compile_error(span, &message).into(),
+    };
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let path = PathBuf::from(root).join(path);
+    let document = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()));
+    let display = path.display().to_string();
+    match document {
+        Ok(document) => {
+            let transform = expand(
+                document,
+                |_| span,
+                |pointer, message| format!("{}: {}: {}", display, pointer, message),
+            );
+            // makes cargo rebuild when the file changes
+            quote! {
+                {
+                    const _: &str = ::std::include_str!(#display);
+                    #transform
+                }
+            }
+        }
+        Err(e) => compile_error(span, &format!("{}: {}", display, e)),
+    }
+    .into()
+}
+
+fn expand(
+    document: Value,
+    span: impl Fn(&str) -> Span,
+    message: impl Fn(&str, &str) -> String,
+) -> TokenStream {
+    // a single rule is shorthand for a rule set with only the default rule
+    let (document, prefix) = match document {
+        Value::Array(_) => {
+            let mut rules = Map::new();
+            rules.insert(DEFAULT_RULE.to_owned(), document);
+            (Value::Object(rules), format!("/{}", DEFAULT_RULE))
+        }
+        document => (document, String::new()),
+    };
+    let errors = RuleSet::validate(&document);
+    if !errors.is_empty() {
+        let errors = errors.iter().map(|e| {
+            let pointer = e.pointer.strip_prefix(&prefix).unwrap_or(&e.pointer);
+            compile_error(span(pointer), &message(pointer, &e.message))
+        });
+        return quote!({ #(#errors)* });
+    }
+    match RuleSet::parse(&document) {
+        Ok(rules) => codegen::rule_set(&rules),
+        Err(e) => compile_error(span(""), &message(&e.pointer, &e.message)),
+    }
+}
+
+/// Reads the single string literal `include_dtl!` is called with.
+fn path_literal(input: TokenStream) -> Result<(String, Span), json::Error> {
+    let mut spans = json::Spans::default();
+    match json::parse(input, &mut spans)? {
+        Value::String(path) => Ok((path, spans.find(""))),
+        _ => Err((spans.find(""), "expected a path to a JSON file".into())),
+    }
+}
+
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    quote_spanned!(span=> ::std::compile_error!(#message);)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_expand_errors() {
+        let tokens = expand(
+            json!([["add", "a", ["lowr", "_S.a"]]]),
+            |_| Span::call_site(),
+            |pointer, message| format!("{}: {}", pointer, message),
+        );
+        let expected = compile_error(Span::call_site(), "/0/2/0: unknown function 'lowr'");
+        assert_eq!(quote!({ #expected }).to_string(), tokens.to_string());
+    }
+}
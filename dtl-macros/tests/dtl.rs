@@ -0,0 +1,187 @@
+use dtl::{entity::EntityValue, rules::RuleSet};
+use dtl_macros::{dtl, include_dtl};
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[test]
+fn test_hello_world() {
+    let transform = dtl!([[
+        "add",
+        "hello",
+        [
+            "concat",
+            "wor",
+            1,
+            ["concat", "l", ["lower", "_S.x.y"], null]
+        ]
+    ]]);
+    let result = transform(&json!({"x": {"y": "D"}}).into());
+    assert_eq!(1, result.len());
+    assert_eq!(json!({"hello": "world"}), result[0]);
+}
+
+/// A rule set compiled by `dtl!`, and the same rule set parsed for the interpreter.
+macro_rules! parity {
+    ($rules:tt) => {
+        (dtl!($rules), RuleSet::parse(&json!($rules)).unwrap())
+    };
+}
+
+#[test]
+fn test_same_as_interpreted() {
+    let cases = vec![
+        (
+            parity!({
+                "default": [
+                    ["add", "name", "_S.name"],
+                    ["add", "upper", ["upper", "_T.name"]],
+                    ["add", "negative", -1.5],
+                    ["add", "uri", "~rhttp://example.com/"],
+                    ["add", "children", ["apply", "child", "_S.children"]]
+                ],
+                "child": [
+                    ["add", "parent", "_P._S.name"],
+                    ["add", "root", "_R._T.upper"],
+                    ["add", "tags", ["map", ["concat", "_.", "_S.name"], ["list", "x", "y"]]]
+                ]
+            }),
+            vec![json!({"name": "a", "children": [{"name": "b"}, {"name": "c"}]})],
+        ),
+        (
+            parity!({"default": [["copy", "*", "_*"], ["remove", "tmp_*"]]}),
+            vec![json!({"_id": "1", "_updated": 2, "a": 1, "tmp_x": 2})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", ["concat", "x_", "_S.key"], "_S.value"],
+                    ["default", "name", "_S.name"],
+                    ["default", "name", "fallback"],
+                    ["rename", "x_a", "renamed"]
+                ]
+            }),
+            vec![json!({"key": "a", "value": "b"})],
+        ),
+        (
+            parity!({"default": [["merge", "_S.a"], ["merge-union", ["list", "_S.b", "_S.c"]]]}),
+            vec![json!({"a": {"x": 1, "y": 2}, "b": {"x": [1, 3]}, "c": {"x": "z"}})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["if", "_S.vip", [["add", "vip", true]], [["add", "vip", false]]],
+                    [
+                        "case",
+                        ["_S.internal", [["add", "kind", "internal"]]],
+                        ["_T.vip", [["add", "kind", "vip"]]],
+                        [true, [["add", "kind", "other"]]]
+                    ]
+                ]
+            }),
+            vec![json!({"vip": true}), json!({"internal": true}), json!({})],
+        ),
+        (
+            parity!({"default": [["filter", "_S.filter"], ["discard", "_S.discard"]]}),
+            vec![json!({"filter": true}), json!({"discard": true}), json!({})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", "_id", "_S._id"],
+                    ["create", "_S.children", ["concat", "_S._id", "-", "_.name"]]
+                ]
+            }),
+            vec![json!({"_id": "1", "children": [{"name": "a"}, "b"]})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["comment", "traces only pass values on here"],
+                    ["add", "a", ["trace", "_S.a"]],
+                    ["trace", "_T"]
+                ]
+            }),
+            vec![json!({"a": 1})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", "a", ["join", "-", ["split", " ", ["trim", "_S.a"]]]],
+                    ["add", "b", ["ljust", 4, ["string", "_S.b"]]],
+                    ["add", "c", ["startswith", "~", ["list", "_S.a", "~x"]]]
+                ]
+            }),
+            vec![json!({"a": " x y", "b": 1.5})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", "a", ["regex", "(\\w+)@", true, "_S.a"]],
+                    ["add", "b", ["regex-sub", "@\\w+", "", "_S.a"]],
+                    ["add", "c", ["regex", "_S.pattern", "_S.a"]],
+                    ["add", "d", ["matches", "*@y*", "_S.a"]]
+                ]
+            }),
+            vec![json!({"a": "a@x, b@y", "pattern": "@(y)"})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", "a", ["boolean", ["concat", "not ", ["string", "_."]], "_S.a"]],
+                    ["add", "b", ["integer", ["list", "_S.b", "2"]]],
+                    ["add", "c", ["decimal", -1, "_S.c"]]
+                ]
+            }),
+            vec![json!({"a": ["True", 1], "b": 1.5, "c": "x"})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", "a", ["plus", 1, "_S.a"]],
+                    ["add", "b", ["divide", ["sum", "_S.a"], ["length", ["list", 1, 2]]]],
+                    ["add", "c", ["mod", -7, "_S.b"]]
+                ]
+            }),
+            vec![json!({"a": [1, 2.5, "~f0.5"], "b": 3})],
+        ),
+        (
+            parity!({
+                "default": [
+                    ["add", "a", ["eq", "_S.a", "~f1.0"]],
+                    ["add", "b", ["and", ["gt", "_S.b", "_S.a"], ["in", 1, "_S.b"]]],
+                    ["add", "c", ["or", ["is-empty", "_S.c"], ["not", "_S.c"]]]
+                ]
+            }),
+            vec![json!({"a": 1, "b": [1, 2], "c": "x"})],
+        ),
+    ];
+    for (i, ((transform, rules), sources)) in cases.into_iter().enumerate() {
+        for source in sources {
+            let message = format!("case {} with source {}", i, source);
+            let source = source.into();
+            assert_eq!(rules.execute(&source), transform(&source), "{}", message);
+        }
+    }
+}
+
+#[test]
+fn test_patterns_reused() {
+    let transform = dtl!([["add", "a", ["regex", "(\\w+)@", true, "_S.a"]]]);
+    let expected: Vec<EntityValue> = vec![json!({"a": ["a", "b"]}).into()];
+    let source = json!({"a": "a@x, b@y"}).into();
+    assert_eq!(expected, transform(&source));
+    // the compiled pattern is reused
+    assert_eq!(expected, transform(&source));
+}
+
+#[test]
+fn test_include_dtl() {
+    let transform: fn(&EntityValue) -> Vec<EntityValue> = include_dtl!("tests/rules.json");
+    let result = transform(&json!({"name": "a", "foo": ["x", "y"]}).into());
+    let expected: Vec<EntityValue> = vec![
+        json!({"_id": "x", "bar": "x", "parent": "a"}).into(),
+        json!({"_id": "y", "bar": "y", "parent": "a"}).into(),
+        json!({"_filtered": true}).into(),
+    ];
+    assert_eq!(expected, result);
+}
@@ -0,0 +1,438 @@
+use regex::Regex;
+
+use crate::entity::EntityValue;
+
+/// A rule is the list of transforms that are executed in order against a single target.
+pub type Rule = Vec<Transform>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transform {
+    /// `["add", "property", expression]`
+    Add(Property, Expression),
+    /// `["default", "property", expression]`, only sets the property if it is missing or null
+    Default(Property, Expression),
+    /// `["rename", "old", "new"]`
+    Rename(String, String),
+    /// `["create", expression]` or `["create", expression, id expression]`, where the
+    /// id expression is evaluated for every created entity as `_.`
+    Create(Expression, Option<Expression>),
+    /// `["merge", expression]`, sets the properties of one or more objects on the target
+    Merge(Expression),
+    /// `["merge-union", expression]`, like merge, but combines conflicting values into lists
+    MergeUnion(Expression),
+    /// `["copy", "pattern" or ["patterns"], "exclude pattern" or ["patterns"]]`
+    Copy(Vec<String>, Vec<String>),
+    /// `["remove", "pattern" or ["patterns"], "exclude pattern" or ["patterns"]]`
+    Remove(Vec<String>, Vec<String>),
+    /// `["filter"]` or `["filter", condition]`, marks the target `_filtered`
+    Filter(Option<Expression>),
+    /// `["discard"]` or `["discard", condition]`, drops the target from the output
+    Discard(Option<Expression>),
+    /// `["if", condition, [then transforms], [else transforms]]`, the else branch is optional
+    If(Expression, Rule, Rule),
+    /// `["case", [condition, [transforms]], ...]`, runs the transforms of the first true condition
+    Case(Vec<(Expression, Rule)>),
+    /// `["comment", ...]`, does nothing
+    Comment,
+    /// `["trace", expression]`, evaluates an `Expression::Trace` only to record its value
+    Trace(Expression),
+}
+
+/// The name of the property `add` and `default` set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    /// `"property"`
+    Name(String),
+    /// `["concat", "x_", "_S.y"]`, properties are only set when it evaluates to a string
+    Expression(Expression),
+}
+
+impl From<&str> for Property {
+    fn from(name: &str) -> Self {
+        Property::Name(name.to_owned())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    /// `"foo"`, `1`, `null`, `true`
+    Literal(EntityValue),
+    /// `["source"]`, `["target"]`, `["current"]`
+    Variable(Variable),
+    /// `["parent", expression]`, evaluates the expression in the context `apply` was called from
+    Parent(Box<Expression>),
+    /// `["root", expression]`, evaluates the expression in the context of the default rule
+    Root(Box<Expression>),
+    /// `["path", "x", "y", expression]`
+    Path(Vec<String>, Box<Expression>),
+    /// `["apply", "rule", expression]`
+    Apply(String, Box<Expression>),
+    /// `["lower", expression]` etc.
+    Call(Function, Vec<Expression>),
+    /// the literal pattern of `regex` and `regex-sub`, compiled when the rule is parsed
+    Pattern(Pattern),
+    /// `["trace", expression]`, records the value of the expression in the trace of the context
+    Trace(TracePoint, Box<Expression>),
+}
+
+/// Where a `trace` is in the rule set, and the DTL it traces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracePoint {
+    /// JSON pointer to the `trace`, starting with the rule name
+    pub pointer: String,
+    pub expression: String,
+}
+
+/// A compiled regular expression, equal to another if they were compiled from the same string.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern, regex::Error> {
+        Regex::new(pattern).map(Pattern)
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    Source,
+    Target,
+    Current,
+}
+
+impl Variable {
+    pub fn from_name(name: &str) -> Option<Variable> {
+        match name {
+            "source" => Some(Variable::Source),
+            "target" => Some(Variable::Target),
+            "current" => Some(Variable::Current),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variable::Source => "source",
+            Variable::Target => "target",
+            Variable::Current => "current",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Function {
+    Abs,
+    And,
+    Boolean,
+    Ceil,
+    Concat,
+    Decimal,
+    Divide,
+    EndsWith,
+    Eq,
+    Float,
+    Floor,
+    Gt,
+    Gte,
+    In,
+    Integer,
+    IsEmpty,
+    IsNotEmpty,
+    IsNull,
+    Join,
+    LJust,
+    LStrip,
+    Length,
+    List,
+    Lower,
+    Lt,
+    Lte,
+    Map,
+    Matches,
+    Max,
+    Min,
+    Minus,
+    Mod,
+    Multiply,
+    Neq,
+    Not,
+    Or,
+    Plus,
+    Pow,
+    RJust,
+    RStrip,
+    Regex,
+    RegexSub,
+    Replace,
+    Round,
+    Split,
+    StartsWith,
+    String,
+    Strip,
+    Substring,
+    Sum,
+    Trim,
+    Upper,
+}
+
+impl Function {
+    pub const ALL: [Function; 52] = [
+        Function::Abs,
+        Function::And,
+        Function::Boolean,
+        Function::Ceil,
+        Function::Concat,
+        Function::Decimal,
+        Function::Divide,
+        Function::EndsWith,
+        Function::Eq,
+        Function::Float,
+        Function::Floor,
+        Function::Gt,
+        Function::Gte,
+        Function::In,
+        Function::Integer,
+        Function::IsEmpty,
+        Function::IsNotEmpty,
+        Function::IsNull,
+        Function::Join,
+        Function::LJust,
+        Function::LStrip,
+        Function::Length,
+        Function::List,
+        Function::Lower,
+        Function::Lt,
+        Function::Lte,
+        Function::Map,
+        Function::Matches,
+        Function::Max,
+        Function::Min,
+        Function::Minus,
+        Function::Mod,
+        Function::Multiply,
+        Function::Neq,
+        Function::Not,
+        Function::Or,
+        Function::Plus,
+        Function::Pow,
+        Function::RJust,
+        Function::RStrip,
+        Function::Regex,
+        Function::RegexSub,
+        Function::Replace,
+        Function::Round,
+        Function::Split,
+        Function::StartsWith,
+        Function::String,
+        Function::Strip,
+        Function::Substring,
+        Function::Sum,
+        Function::Trim,
+        Function::Upper,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Function> {
+        Function::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// The minimum and maximum number of arguments the function accepts.
+    /// Functions on strings take the strings as their last argument.
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            Function::And
+            | Function::Concat
+            | Function::Max
+            | Function::Min
+            | Function::Or
+            | Function::Sum => (1, None),
+            Function::List => (0, None),
+            Function::Abs
+            | Function::Ceil
+            | Function::Floor
+            | Function::IsEmpty
+            | Function::IsNotEmpty
+            | Function::IsNull
+            | Function::Length
+            | Function::Lower
+            | Function::Not
+            | Function::Trim
+            | Function::Upper => (1, Some(1)),
+            Function::LStrip | Function::RStrip | Function::Round | Function::Strip => (1, Some(2)),
+            Function::Boolean
+            | Function::Decimal
+            | Function::Float
+            | Function::Integer
+            | Function::String => (1, Some(2)),
+            Function::Divide
+            | Function::EndsWith
+            | Function::Eq
+            | Function::Gt
+            | Function::Gte
+            | Function::In
+            | Function::Join
+            | Function::Lt
+            | Function::Lte
+            | Function::Map
+            | Function::Matches
+            | Function::Minus
+            | Function::Mod
+            | Function::Multiply
+            | Function::Neq
+            | Function::Plus
+            | Function::Pow
+            | Function::Split
+            | Function::StartsWith => (2, Some(2)),
+            Function::LJust | Function::RJust | Function::Regex => (2, Some(3)),
+            Function::RegexSub | Function::Replace | Function::Substring => (3, Some(3)),
+        }
+    }
+
+    /// Whether the function converts values and takes a default for the ones it can't, which is
+    /// evaluated for each of them with the value as `_.`.
+    pub fn is_coercion(&self) -> bool {
+        matches!(
+            self,
+            Function::Boolean
+                | Function::Decimal
+                | Function::Float
+                | Function::Integer
+                | Function::String
+        )
+    }
+
+    /// Whether the function does arithmetic, all its arguments are numbers or lists of them.
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            Function::Abs
+                | Function::Ceil
+                | Function::Divide
+                | Function::Floor
+                | Function::Max
+                | Function::Min
+                | Function::Minus
+                | Function::Mod
+                | Function::Multiply
+                | Function::Plus
+                | Function::Pow
+                | Function::Round
+                | Function::Sum
+        )
+    }
+
+    /// Whether the argument is a regular expression, compiled up front when it's a literal.
+    pub fn is_pattern(&self, index: usize) -> bool {
+        matches!((self, index), (Function::Regex | Function::RegexSub, 0))
+    }
+
+    /// Checks a literal argument, returns a message if the function can't use it.
+    pub fn check_literal(&self, index: usize, literal: &EntityValue) -> Option<String> {
+        let expects = |what: &str| Some(format!("'{}' expects {}", self.name(), what));
+        match (self, index, literal) {
+            (_, _, EntityValue::Null) => None,
+            (f, _, EntityValue::Number(_) | EntityValue::Decimal(_)) if f.is_arithmetic() => None,
+            (f, _, _) if f.is_arithmetic() => expects("a number"),
+            (Function::Lower | Function::Upper | Function::Trim, _, EntityValue::String(_)) => None,
+            (Function::Lower | Function::Upper | Function::Trim, _, _) => expects("a string"),
+            (Function::Map, 1, _) => expects("a list"),
+            (Function::Substring, 0 | 1, EntityValue::Number(_)) => None,
+            (Function::Substring, 0 | 1, _) => expects("a number"),
+            (Function::LJust | Function::RJust, 0, EntityValue::Number(_)) => None,
+            (Function::LJust | Function::RJust, 0, _) => expects("a number"),
+            (
+                Function::EndsWith
+                | Function::Join
+                | Function::Matches
+                | Function::Regex
+                | Function::Split
+                | Function::StartsWith,
+                0,
+                EntityValue::String(_),
+            )
+            | (Function::RegexSub | Function::Replace, 0 | 1, EntityValue::String(_)) => None,
+            (
+                Function::EndsWith
+                | Function::Join
+                | Function::Matches
+                | Function::Regex
+                | Function::Split
+                | Function::StartsWith,
+                0,
+                _,
+            )
+            | (Function::RegexSub | Function::Replace, 0 | 1, _) => expects("a string"),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Abs => "abs",
+            Function::And => "and",
+            Function::Boolean => "boolean",
+            Function::Ceil => "ceil",
+            Function::Concat => "concat",
+            Function::Decimal => "decimal",
+            Function::Divide => "divide",
+            Function::EndsWith => "endswith",
+            Function::Eq => "eq",
+            Function::Float => "float",
+            Function::Floor => "floor",
+            Function::Gt => "gt",
+            Function::Gte => "gte",
+            Function::In => "in",
+            Function::Integer => "integer",
+            Function::IsEmpty => "is-empty",
+            Function::IsNotEmpty => "is-not-empty",
+            Function::IsNull => "is-null",
+            Function::Join => "join",
+            Function::LJust => "ljust",
+            Function::LStrip => "lstrip",
+            Function::Length => "length",
+            Function::List => "list",
+            Function::Lower => "lower",
+            Function::Lt => "lt",
+            Function::Lte => "lte",
+            Function::Map => "map",
+            Function::Matches => "matches",
+            Function::Max => "max",
+            Function::Min => "min",
+            Function::Minus => "minus",
+            Function::Mod => "mod",
+            Function::Multiply => "multiply",
+            Function::Neq => "neq",
+            Function::Not => "not",
+            Function::Or => "or",
+            Function::Plus => "plus",
+            Function::Pow => "pow",
+            Function::RJust => "rjust",
+            Function::RStrip => "rstrip",
+            Function::Regex => "regex",
+            Function::RegexSub => "regex-sub",
+            Function::Replace => "replace",
+            Function::Round => "round",
+            Function::Split => "split",
+            Function::StartsWith => "startswith",
+            Function::String => "string",
+            Function::Strip => "strip",
+            Function::Substring => "substring",
+            Function::Sum => "sum",
+            Function::Trim => "trim",
+            Function::Upper => "upper",
+        }
+    }
+}
@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Expression, Function, Property, Rule, Transform, Variable},
+    dtl::*,
+    entity::{Entity, EntityValue},
+    error::Error,
+    eval::{call_pattern, coerce, resolve, trace, Context, Trace},
+    rules::{RuleSet, DEFAULT_RULE},
+};
+
+type Closure = Box<dyn Fn(&CompiledRuleSet, &Context) -> EntityValue + Send + Sync>;
+type CompiledTransform = Box<dyn Fn(&CompiledRuleSet, &Context, &mut Target) + Send + Sync>;
+
+/// A rule set compiled into a tree of closures, with rule and function lookups,
+/// path splitting and constant folding done once instead of for every entity.
+pub struct CompiledRuleSet {
+    rules: Vec<Vec<CompiledTransform>>,
+    names: HashMap<String, usize>,
+}
+
+impl CompiledRuleSet {
+    /// Runs the default rule against a source entity.
+    pub fn execute(&self, source: &EntityValue) -> Vec<EntityValue> {
+        self.apply(DEFAULT_RULE, source)
+    }
+
+    /// Runs the default rule against an entity, see `Target::into_entities`.
+    pub fn transform(&self, source: &Entity) -> Result<Vec<Entity>, Error> {
+        let value = source.clone().into();
+        match self.names.get(DEFAULT_RULE) {
+            Some(index) => self
+                .target(*index, &Context::new(&value))
+                .into_entities(source),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Like `execute`, but records the values of `trace` transforms and expressions.
+    pub fn execute_traced(&self, source: &EntityValue, trace: &Trace) -> Vec<EntityValue> {
+        match self.names.get(DEFAULT_RULE) {
+            Some(index) => self.run(*index, &Context::new(source).with_trace(trace)),
+            None => vec![],
+        }
+    }
+
+    /// Like `execute`, but fails if the default rule creates something that isn't an entity.
+    pub fn try_execute(&self, source: &EntityValue) -> Result<Vec<EntityValue>, Error> {
+        match self.names.get(DEFAULT_RULE) {
+            Some(index) => self.target(*index, &Context::new(source)).finish(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Runs a named rule against a source entity, returns nothing if the rule doesn't exist.
+    pub fn apply(&self, name: &str, source: &EntityValue) -> Vec<EntityValue> {
+        match self.names.get(name) {
+            Some(index) => self.run(*index, &Context::new(source)),
+            None => vec![],
+        }
+    }
+
+    fn run(&self, index: usize, context: &Context) -> Vec<EntityValue> {
+        self.target(index, context).output()
+    }
+
+    fn target(&self, index: usize, context: &Context) -> Target {
+        let mut target = Target::new();
+        for transform in &self.rules[index] {
+            transform(self, context, &mut target);
+        }
+        target
+    }
+}
+
+enum Compiled {
+    Constant(EntityValue),
+    Dynamic(Closure),
+}
+
+impl Compiled {
+    fn into_closure(self) -> Closure {
+        match self {
+            Compiled::Constant(value) => Box::new(move |_, _| value.clone()),
+            Compiled::Dynamic(closure) => closure,
+        }
+    }
+}
+
+pub fn compile(rules: &RuleSet) -> CompiledRuleSet {
+    let names: HashMap<String, usize> = rules
+        .iter()
+        .enumerate()
+        .map(|(index, (name, _))| (name.to_owned(), index))
+        .collect();
+    let compiled = rules
+        .iter()
+        .map(|(_, rule)| compile_rule(rule, &names))
+        .collect();
+    CompiledRuleSet {
+        rules: compiled,
+        names,
+    }
+}
+
+fn compile_rule(rule: &Rule, names: &HashMap<String, usize>) -> Vec<CompiledTransform> {
+    rule.iter()
+        .map(|transform| compile_transform(transform, names))
+        .collect()
+}
+
+fn compile_transform(transform: &Transform, names: &HashMap<String, usize>) -> CompiledTransform {
+    match transform {
+        Transform::Add(property, expression) => {
+            compile_set(Target::add, property, expression, names)
+        }
+        Transform::Default(property, expression) => {
+            compile_set(Target::set_default, property, expression, names)
+        }
+        Transform::Rename(from, to) => {
+            let (from, to) = (from.clone(), to.clone());
+            Box::new(move |_, _, target| target.rename(&from, &to))
+        }
+        Transform::Create(expression, None) => compile_update(Target::create, expression, names),
+        Transform::Create(expression, Some(id)) => {
+            let value = compile_expression(expression, names).into_closure();
+            let id = compile_expression(id, names).into_closure();
+            Box::new(move |rules, context, target| {
+                let value = {
+                    let context = context.with_target(target.entity());
+                    set_ids(value(rules, &context), |entity| {
+                        id(rules, &context.with_current(entity))
+                    })
+                };
+                target.create(value);
+            })
+        }
+        Transform::Merge(expression) => compile_update(Target::merge, expression, names),
+        Transform::MergeUnion(expression) => compile_update(Target::merge_union, expression, names),
+        Transform::Copy(include, exclude) => {
+            let (include, exclude) = (include.clone(), exclude.clone());
+            Box::new(move |_, context, target| target.copy(&include, &exclude, context.source))
+        }
+        Transform::Remove(include, exclude) => {
+            let (include, exclude) = (include.clone(), exclude.clone());
+            Box::new(move |_, _, target| target.remove(&include, &exclude))
+        }
+        Transform::Filter(condition) => compile_conditional(Target::filter, condition, names),
+        Transform::Discard(condition) => compile_conditional(Target::discard, condition, names),
+        Transform::If(condition, then, otherwise) => compile_case(
+            &[
+                (condition.clone(), then.clone()),
+                (
+                    Expression::Literal(EntityValue::Bool(true)),
+                    otherwise.clone(),
+                ),
+            ],
+            names,
+        ),
+        Transform::Case(branches) => compile_case(branches, names),
+        Transform::Comment => Box::new(|_, _, _| {}),
+        Transform::Trace(expression) => {
+            let value = compile_expression(expression, names).into_closure();
+            Box::new(move |rules, context, target| {
+                value(rules, &context.with_target(target.entity()));
+            })
+        }
+    }
+}
+
+fn compile_conditional(
+    action: fn(&mut Target),
+    condition: &Option<Expression>,
+    names: &HashMap<String, usize>,
+) -> CompiledTransform {
+    let Some(condition) = condition else {
+        return Box::new(move |_, _, target| action(target));
+    };
+    match compile_expression(condition, names) {
+        Compiled::Constant(value) if is_true(&value) => {
+            Box::new(move |_, _, target| action(target))
+        }
+        Compiled::Constant(_) => Box::new(|_, _, _| {}),
+        Compiled::Dynamic(condition) => Box::new(move |rules, context, target| {
+            if is_true(&condition(rules, &context.with_target(target.entity()))) {
+                action(target);
+            }
+        }),
+    }
+}
+
+/// Compiles the branches of `case` and `if`, those with constant conditions are chosen up front.
+fn compile_case(
+    branches: &[(Expression, Rule)],
+    names: &HashMap<String, usize>,
+) -> CompiledTransform {
+    let mut compiled: Vec<(Closure, Vec<CompiledTransform>)> = Vec::new();
+    for (condition, rule) in branches {
+        match compile_expression(condition, names) {
+            Compiled::Constant(value) if !is_true(&value) => continue,
+            Compiled::Constant(_) => {
+                let rule = compile_rule(rule, names);
+                if compiled.is_empty() {
+                    return Box::new(move |rules, context, target| {
+                        rule.iter().for_each(|t| t(rules, context, target))
+                    });
+                }
+                compiled.push((Box::new(|_, _| EntityValue::Bool(true)), rule));
+                break;
+            }
+            Compiled::Dynamic(condition) => compiled.push((condition, compile_rule(rule, names))),
+        }
+    }
+    Box::new(move |rules, context, target| {
+        let branch = compiled.iter().find(|(condition, _)| {
+            is_true(&condition(rules, &context.with_target(target.entity())))
+        });
+        if let Some((_, rule)) = branch {
+            for transform in rule {
+                transform(rules, context, target);
+            }
+        }
+    })
+}
+
+fn compile_update(
+    updater: fn(&mut Target, EntityValue),
+    expression: &Expression,
+    names: &HashMap<String, usize>,
+) -> CompiledTransform {
+    let value = compile_expression(expression, names).into_closure();
+    Box::new(move |rules, context, target| {
+        let value = value(rules, &context.with_target(target.entity()));
+        updater(target, value);
+    })
+}
+
+fn compile_set(
+    setter: fn(&mut Target, &str, EntityValue),
+    property: &Property,
+    expression: &Expression,
+    names: &HashMap<String, usize>,
+) -> CompiledTransform {
+    let value = compile_expression(expression, names).into_closure();
+    let name = match property {
+        Property::Name(name) => Compiled::Constant(EntityValue::String(name.clone())),
+        Property::Expression(expression) => compile_expression(expression, names),
+    };
+    match name {
+        Compiled::Constant(EntityValue::String(name)) => Box::new(move |rules, context, target| {
+            let value = value(rules, &context.with_target(target.entity()));
+            setter(target, &name, value);
+        }),
+        // only string names set a property
+        Compiled::Constant(_) => Box::new(|_, _, _| {}),
+        Compiled::Dynamic(name) => Box::new(move |rules, context, target| {
+            let context = context.with_target(target.entity());
+            let (name, value) = (name(rules, &context), value(rules, &context));
+            if let EntityValue::String(name) = name {
+                setter(target, &name, value);
+            }
+        }),
+    }
+}
+
+fn compile_expression(expression: &Expression, names: &HashMap<String, usize>) -> Compiled {
+    match expression {
+        Expression::Literal(value) => Compiled::Constant(value.clone()),
+        Expression::Variable(variable) => Compiled::Dynamic(variable_closure(*variable)),
+        Expression::Parent(value) => {
+            let value = compile_expression(value, names).into_closure();
+            Compiled::Dynamic(Box::new(move |rules, context| match context.parent {
+                Some(parent) => value(rules, parent),
+                None => EntityValue::Null,
+            }))
+        }
+        Expression::Root(value) => {
+            let value = compile_expression(value, names).into_closure();
+            Compiled::Dynamic(Box::new(move |rules, context| value(rules, context.root())))
+        }
+        Expression::Path(segments, value) => {
+            let segments = segments.clone();
+            // walk the variable in place instead of cloning all of it first
+            if let Expression::Variable(variable) = value.as_ref() {
+                let variable = *variable;
+                return Compiled::Dynamic(Box::new(move |_, context| {
+                    let value = match variable {
+                        Variable::Source => Some(context.source),
+                        Variable::Target => context.target,
+                        Variable::Current => context.current,
+                    };
+                    value
+                        .map_or(&EntityValue::Null, |v| path_segments(&segments, v))
+                        .clone()
+                }));
+            }
+            match compile_expression(value, names) {
+                Compiled::Constant(value) => {
+                    Compiled::Constant(path_segments(&segments, &value).clone())
+                }
+                Compiled::Dynamic(value) => Compiled::Dynamic(Box::new(move |rules, context| {
+                    path_segments(&segments, &value(rules, context)).clone()
+                })),
+            }
+        }
+        Expression::Apply(rule, value) => {
+            let Some(&index) = names.get(rule) else {
+                return Compiled::Constant(EntityValue::Array(vec![]));
+            };
+            let value = compile_expression(value, names).into_closure();
+            Compiled::Dynamic(Box::new(move |rules, context| {
+                apply(
+                    |item| rules.run(index, &Context::new(item).with_parent(context)),
+                    &value(rules, context),
+                )
+            }))
+        }
+        Expression::Call(Function::Map, args) => {
+            let mut args = args
+                .iter()
+                .map(|arg| compile_expression(arg, names).into_closure());
+            let (Some(function), Some(items)) = (args.next(), args.next()) else {
+                return Compiled::Constant(EntityValue::Null);
+            };
+            Compiled::Dynamic(Box::new(move |rules, context| {
+                map(
+                    |item| function(rules, &context.with_current(item)),
+                    &items(rules, context),
+                )
+            }))
+        }
+        // a default that isn't constant is evaluated for every value that can't be converted
+        Expression::Call(function, args) if function.is_coercion() && args.len() == 2 => {
+            let function = *function;
+            let Compiled::Dynamic(default) = compile_expression(&args[0], names) else {
+                return compile_call(resolve(function), args, names);
+            };
+            let source = compile_expression(&args[1], names).into_closure();
+            Compiled::Dynamic(Box::new(move |rules, context| {
+                let default = |value: &EntityValue| default(rules, &context.with_current(value));
+                coerce(function, Some(&default), &source(rules, context))
+            }))
+        }
+        Expression::Call(function, args) => {
+            let function = *function;
+            match args.as_slice() {
+                // the pattern the parser compiled is shared by every call
+                [Expression::Pattern(pattern), args @ ..] => {
+                    let pattern = pattern.clone();
+                    compile_call(
+                        move |values| call_pattern(function, &pattern, values),
+                        args,
+                        names,
+                    )
+                }
+                args => compile_call(resolve(function), args, names),
+            }
+        }
+        Expression::Pattern(pattern) => {
+            Compiled::Constant(EntityValue::String(pattern.as_str().to_owned()))
+        }
+        // never constant, the value is recorded every time it is evaluated
+        Expression::Trace(point, value) => {
+            let (pointer, expression) = (point.pointer.clone(), point.expression.clone());
+            let value = compile_expression(value, names).into_closure();
+            Compiled::Dynamic(Box::new(move |rules, context| {
+                trace(&pointer, &expression, value(rules, context), context)
+            }))
+        }
+    }
+}
+
+/// Calls the function right away if all the arguments are constant.
+fn compile_call(
+    implementation: impl Fn(&[EntityValue]) -> EntityValue + Send + Sync + 'static,
+    args: &[Expression],
+    names: &HashMap<String, usize>,
+) -> Compiled {
+    let args: Vec<Compiled> = args
+        .iter()
+        .map(|arg| compile_expression(arg, names))
+        .collect();
+    if args.iter().all(|arg| matches!(arg, Compiled::Constant(_))) {
+        let values: Vec<EntityValue> = args
+            .into_iter()
+            .filter_map(|arg| match arg {
+                Compiled::Constant(value) => Some(value),
+                Compiled::Dynamic(_) => None,
+            })
+            .collect();
+        return Compiled::Constant(implementation(&values));
+    }
+    let args: Vec<Closure> = args.into_iter().map(Compiled::into_closure).collect();
+    Compiled::Dynamic(Box::new(move |rules, context| {
+        let values: Vec<EntityValue> = args.iter().map(|arg| arg(rules, context)).collect();
+        implementation(&values)
+    }))
+}
+
+fn variable_closure(variable: Variable) -> Closure {
+    match variable {
+        Variable::Source => Box::new(|_, context| context.source.clone()),
+        Variable::Target => {
+            Box::new(|_, context| context.target.cloned().unwrap_or(EntityValue::Null))
+        }
+        Variable::Current => {
+            Box::new(|_, context| context.current.cloned().unwrap_or(EntityValue::Null))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    /// Creates entities from the children and the extra values of the source.
+    const CREATE: &str = r#"
+        {
+          "default": [
+            ["copy", "*"],
+            ["create", ["apply", "child", "_S.children"], ["concat", "_S._id", "-", "_.name"]],
+            ["create", "_S.extra"]
+          ],
+          "child": [
+            ["add", "name", "_S.name"]
+          ]
+        }
+        "#;
+
+    /// Rule sets and the sources to run them on, the compiled rules must give the same
+    /// targets as the interpreted ones for every source.
+    fn parity_cases() -> Vec<(&'static str, Vec<serde_json::Value>)> {
+        vec![
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", "hello",
+                      ["concat", "wor", 1,
+                        ["concat", "l", ["lower", "_S.x.y"], null]
+                      ]
+                    ]
+                  ]
+                }
+                "#,
+                vec![json!({"x": {"y": "D"}})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["create", ["apply", "foo", "_S.foo"]],
+                    ["add", "name", "_S.name"],
+                    ["add", "upper", ["upper", "_T.name"]],
+                    ["add", "children", ["apply", "child", "_S.children"]]
+                  ],
+                  "foo": [
+                    ["add", "_id", "_S"],
+                    ["add", "bar", "_S"]
+                  ],
+                  "child": [
+                    ["add", "parent", "_P._S.name"],
+                    ["add", "root", "_R._T.upper"],
+                    ["add", "tags", ["map", ["concat", "_.", "_S.name"], ["list", "x", "y"]]]
+                  ]
+                }
+                "#,
+                vec![json!({
+                    "foo": ["bar", "baz"],
+                    "name": "a",
+                    "children": [{"name": "b"}, {"name": "c"}]
+                })],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["copy", "*", "_*"],
+                    ["remove", ["tmp_*", "_id"]]
+                  ]
+                }
+                "#,
+                vec![json!({"_id": "1", "_updated": 2, "name": "a", "tmp_x": "x"})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", ["concat", "x_", "_S.key"], "_S.value"],
+                    ["add", ["concat", "y_", "z"], 1],
+                    ["add", ["list", "a"], 2],
+                    ["default", "name", "_S.name"],
+                    ["default", "name", "fallback"],
+                    ["rename", "x_a", "renamed"]
+                  ]
+                }
+                "#,
+                vec![json!({"key": "a", "value": "b"})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["merge", "_S.a"],
+                    ["merge-union", ["list", "_S.b", "_S.c"]]
+                  ]
+                }
+                "#,
+                vec![json!({"a": {"x": 1, "y": 2}, "b": {"x": [1, 3]}, "c": {"x": "z"}})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["if", "_S.vip", [["add", "vip", true]], [["add", "vip", false]]],
+                    ["if", ["concat", "a"], [["add", "never", true]]],
+                    ["case",
+                      ["_S.internal", [["add", "kind", "internal"]]],
+                      [false, [["add", "kind", "never"]]],
+                      ["_T.vip", [["add", "kind", "vip"]]],
+                      [true, [["add", "kind", "other"]]]
+                    ]
+                  ]
+                }
+                "#,
+                vec![json!({"vip": true}), json!({"internal": true}), json!({})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["filter", "_S.filter"],
+                    ["discard", "_S.discard"],
+                    ["filter", false],
+                    ["add", "children", ["apply", "child", "_S.children"]]
+                  ],
+                  "child": [
+                    ["add", "name", "_S.name"],
+                    ["filter", "_S.filter"]
+                  ]
+                }
+                "#,
+                vec![
+                    json!({"children": [{"name": "a"}, {"name": "b", "filter": true}]}),
+                    json!({"filter": true}),
+                    json!({"discard": true}),
+                ],
+            ),
+            (
+                CREATE,
+                vec![
+                    json!({"_id": "1", "children": [{"name": "a"}], "extra": [{"_id": "2"}, "x"]}),
+                ],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", "a", ["split", ",", ["strip", "_S.a"]]],
+                    ["add", "b", ["rjust", 3, "0", ["string", ["length", "_S.a"]]]],
+                    ["add", "c", ["substring", 1, -1, ["upper", "const"]]],
+                    ["add", "d", ["endswith", "x", ["list", "_S.a", "box"]]]
+                  ]
+                }
+                "#,
+                vec![json!({"a": " x,y,z "})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", "a", ["regex", "(\\w)=(\\d)", true, "_S.a"]],
+                    ["add", "b", ["regex-sub", "\\d", "*", ["list", "_S.a", "x1"]]],
+                    ["add", "c", ["regex", "_S.pattern", "_S.a"]],
+                    ["add", "d", ["regex", ["concat", "["], "_S.a"]],
+                    ["add", "e", ["matches", "*=2*", ["split", ",", "_S.a"]]],
+                    ["add", "f", ["regex-sub", "a", "b", "aa"]]
+                  ]
+                }
+                "#,
+                vec![json!({"a": "a=1,b=2", "pattern": "[a-z]=2"})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", "a", ["boolean", ["string", "_."], "_S.a"]],
+                    ["add", "b", ["integer", 0, "_S.b"]],
+                    ["add", "c", ["float", ["integer", "1"]]],
+                    ["add", "d", ["decimal", ["list", "_S.b", "1.5"]]],
+                    ["add", "e", ["string", "_S.b"]]
+                  ]
+                }
+                "#,
+                vec![json!({"a": ["true", 1, "FALSE"], "b": [1.5, "x", "~f2.5"]})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", "a", ["plus", "_S.a", ["multiply", 2, 3]]],
+                    ["add", "b", ["round", 1, ["divide", "_S.a", "_S.b"]]],
+                    ["add", "c", ["sum", "_S.a", "_S.b", ["abs", -1]]],
+                    ["add", "d", ["min", ["floor", "_S.a"]]],
+                    ["add", "e", ["pow", "_S.b", ["ceil", 1.5]]]
+                  ]
+                }
+                "#,
+                vec![json!({"a": [1, 2.25, "~f3.5"], "b": 3})],
+            ),
+            (
+                r#"
+                {
+                  "default": [
+                    ["add", "a", ["eq", "_S.a", ["plus", 1, 1]]],
+                    ["add", "b", ["and", ["lt", "_S.a", "_S.b"], ["not", ["is-null", "_S.b"]]]],
+                    ["add", "c", ["or", ["in", 3, "_S.b"], ["is-empty", "_S.c"]]],
+                    ["add", "d", ["gte", "~t2024-01-01T00:00:00.000000000+00:00", "~t2024-01-01"]]
+                  ]
+                }
+                "#,
+                vec![json!({"a": "~f2", "b": [1, 3], "c": {}})],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_compile_same_as_interpreted() {
+        for (rules, sources) in parity_cases() {
+            let interpreted = RuleSet::parse_str(rules).unwrap();
+            let compiled = compile(&interpreted);
+            for source in sources {
+                let source = source.into();
+                assert_eq!(
+                    interpreted.execute(&source),
+                    compiled.execute(&source),
+                    "{}",
+                    rules
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_create_errors() {
+        let rules = RuleSet::parse_str(CREATE).unwrap();
+        let source = json!({"_id": "1", "extra": "x"}).into();
+        assert_eq!(
+            rules.try_execute(&source),
+            compile(&rules).try_execute(&source)
+        );
+    }
+
+    #[test]
+    fn test_compile_transform() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["copy", "*"],
+                ["add", "_ts", 1],
+                ["create", ["apply", "child", "_S.children"]]
+            ],
+            "child": [["copy", "*"]]
+        }))
+        .unwrap();
+        let source = serde_json::from_value(json!({
+            "_id": "1",
+            "_deleted": true,
+            "children": [{"_id": "2"}, {"_id": "3", "_previous": 1}]
+        }))
+        .unwrap();
+        let transformed = rules.transform(&source).unwrap();
+        assert_eq!(3, transformed.len());
+        assert_eq!(transformed, compile(&rules).transform(&source).unwrap());
+    }
+
+    #[test]
+    fn test_compile_trace() {
+        let rules = RuleSet::parse_str(
+            r#"
+            {
+              "default": [
+                ["comment", "constant traces are still recorded"],
+                ["add", "a", ["trace", ["upper", "a"]]],
+                ["add", "b", ["apply", "b", ["trace", "_S"]]],
+                ["trace", "_T"]
+              ],
+              "b": [
+                ["add", "c", ["trace", ["parent", "_T.a"]]]
+              ]
+            }
+            "#,
+        )
+        .unwrap();
+        let source = json!({"x": 1}).into();
+        let (interpreted, compiled) = (Trace::new(), Trace::new());
+        assert_eq!(
+            rules.execute_traced(&source, &interpreted),
+            compile(&rules).execute_traced(&source, &compiled)
+        );
+        assert_eq!(4, interpreted.events().len());
+        assert_eq!(interpreted.into_events(), compiled.into_events());
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let names = HashMap::new();
+        let expression =
+            crate::parser::parse_expression(&json!(["upper", ["concat", "a", "b"]])).unwrap();
+        match compile_expression(&expression, &names) {
+            Compiled::Constant(value) => assert_eq!(json!("AB"), value),
+            Compiled::Dynamic(_) => panic!("expected a constant"),
+        }
+    }
+}
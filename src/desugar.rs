@@ -0,0 +1,287 @@
+use serde_json::{json, Value};
+
+/// Rewrites the shorthand notation of a DTL rule into its explicit form, e.g.
+///
+/// ```json
+/// ["add", "hello", ["concat", "wor", 1, ["lower", "_S.x.y"], null]]
+/// ```
+///
+/// becomes
+///
+/// ```json
+/// ["add", "hello", ["concat", ["string_literal", "wor"], ["number_literal", 1],
+///   ["lower", ["path", "x", "y", ["source"]]], ["null_literal"]]]
+/// ```
+///
+/// Every node keeps its position in the document, so a JSON pointer into the
+/// desugared rule is also valid for the original.
+pub fn desugar(rule: &Value) -> Value {
+    match rule {
+        Value::Array(transforms) => {
+            Value::Array(transforms.iter().map(desugar_transform).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+pub fn desugar_expression(expression: &Value) -> Value {
+    match expression {
+        Value::Null => json!(["null_literal"]),
+        Value::Bool(b) => json!(["boolean_literal", b]),
+        Value::Number(n) => json!(["number_literal", n]),
+        Value::String(s) => desugar_string(s),
+        Value::Array(items) => match items.split_first() {
+            Some((Value::String(name), args)) => {
+                desugar_call(name, args, expression_raw_args(name, args.len()))
+            }
+            _ => expression.clone(),
+        },
+        Value::Object(_) => expression.clone(),
+    }
+}
+
+fn desugar_transform(transform: &Value) -> Value {
+    match transform {
+        Value::Array(items) => match items.split_first() {
+            Some((Value::String(name), args)) if name == "if" => {
+                let mut call = vec![Value::String(name.clone())];
+                for (i, arg) in args.iter().enumerate() {
+                    call.push(if i == 0 {
+                        desugar_expression(arg)
+                    } else {
+                        desugar(arg)
+                    });
+                }
+                Value::Array(call)
+            }
+            Some((Value::String(name), args)) if name == "case" => {
+                let mut call = vec![Value::String(name.clone())];
+                call.extend(
+                    args.iter()
+                        .map(|branch| match branch.as_array().map(Vec::as_slice) {
+                            Some([condition, rule]) => {
+                                json!([desugar_expression(condition), desugar(rule)])
+                            }
+                            _ => branch.clone(),
+                        }),
+                );
+                Value::Array(call)
+            }
+            Some((Value::String(name), args)) => {
+                desugar_call(name, args, transform_raw_args(name, args))
+            }
+            _ => transform.clone(),
+        },
+        other => other.clone(),
+    }
+}
+
+/// Keeps the first `raw` arguments as they are (property names, rule names,
+/// path segments) and desugars the rest as expressions.
+fn desugar_call(name: &str, args: &[Value], raw: usize) -> Value {
+    let mut call = vec![Value::String(name.to_owned())];
+    for (i, arg) in args.iter().enumerate() {
+        call.push(if i < raw {
+            arg.clone()
+        } else {
+            desugar_expression(arg)
+        });
+    }
+    Value::Array(call)
+}
+
+fn transform_raw_args(name: &str, args: &[Value]) -> usize {
+    match name {
+        // the property name can also be computed by an expression
+        "add" | "default" => match args.first() {
+            Some(Value::Array(_)) => 0,
+            _ => 1,
+        },
+        "copy" | "remove" | "rename" => 2,
+        "comment" => args.len(),
+        _ => 0,
+    }
+}
+
+fn expression_raw_args(name: &str, count: usize) -> usize {
+    match name {
+        "string_literal" | "number_literal" | "null_literal" | "boolean_literal"
+        | "transit_literal" => count,
+        "apply" => 1,
+        "path" => count.saturating_sub(1),
+        _ => 0,
+    }
+}
+
+fn desugar_string(s: &str) -> Value {
+    if let Some(rest) = s.strip_prefix('~') {
+        return if rest.starts_with('~') {
+            // "~~" escapes a leading tilde
+            json!(["string_literal", rest])
+        } else {
+            json!(["transit_literal", s])
+        };
+    }
+    desugar_variable(s).unwrap_or_else(|| json!(["string_literal", s]))
+}
+
+/// Rewrites `"_S.x"`, `"_T.x"` and `"_.x"` into paths, `"_P."` and `"_R."`
+/// evaluate the rest of the variable in the parent and root context.
+fn desugar_variable(s: &str) -> Option<Value> {
+    for (prefix, scope) in [("_P.", "parent"), ("_R.", "root")] {
+        if let Some(rest) = s.strip_prefix(prefix) {
+            return Some(json!([scope, desugar_variable(rest)?]));
+        }
+    }
+    let variables = [("_S", "source"), ("_T", "target"), ("_", "current")];
+    for (prefix, variable) in variables {
+        if let Some(rest) = s.strip_prefix(prefix) {
+            // "_S" and "_T" refer to the whole value, but the current value is spelled "_."
+            if (rest.is_empty() && prefix != "_") || (rest == "." && prefix == "_") {
+                return Some(json!([variable]));
+            }
+            if let Some(path) = rest.strip_prefix('.') {
+                let mut call = vec![json!("path")];
+                call.extend(path.split('.').map(|segment| json!(segment)));
+                call.push(json!([variable]));
+                return Some(Value::Array(call));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_desugar_documented_example() {
+        let rule = json!([[
+            "add",
+            "hello",
+            [
+                "concat",
+                "wor",
+                1,
+                ["concat", "l", ["lower", "_S.x.y"], null]
+            ]
+        ]]);
+        let expected = json!([[
+            "add",
+            "hello",
+            [
+                "concat",
+                ["string_literal", "wor"],
+                ["number_literal", 1],
+                [
+                    "concat",
+                    ["string_literal", "l"],
+                    ["lower", ["path", "x", "y", ["source"]]],
+                    ["null_literal"]
+                ]
+            ]
+        ]]);
+        assert_eq!(expected, desugar(&rule));
+    }
+
+    #[test]
+    fn test_desugar_property_names() {
+        assert_eq!(
+            json!([
+                ["add", "_S.a", ["source"]],
+                [
+                    "add",
+                    [
+                        "concat",
+                        ["string_literal", "x_"],
+                        ["path", "a", ["source"]]
+                    ],
+                    ["null_literal"]
+                ],
+                ["rename", "_S.a", "b"]
+            ]),
+            desugar(&json!([
+                ["add", "_S.a", "_S"],
+                ["add", ["concat", "x_", "_S.a"], null],
+                ["rename", "_S.a", "b"]
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_desugar_is_idempotent() {
+        let rule = json!([
+            ["create", ["apply", "foo", ["path", "foo", ["source"]]]],
+            ["filter"]
+        ]);
+        assert_eq!(rule, desugar(&rule));
+        assert_eq!(
+            desugar(&desugar(&json!([["add", "a", "_T.b"]]))),
+            desugar(&json!([["add", "a", "_T.b"]]))
+        );
+    }
+
+    #[test]
+    fn test_desugar_variables() {
+        assert_eq!(json!(["source"]), desugar_expression(&json!("_S")));
+        assert_eq!(
+            json!(["path", "b", ["target"]]),
+            desugar_expression(&json!("_T.b"))
+        );
+        assert_eq!(json!(["current"]), desugar_expression(&json!("_.")));
+        assert_eq!(
+            json!(["path", "a", "b", ["current"]]),
+            desugar_expression(&json!("_.a.b"))
+        );
+        assert_eq!(
+            json!(["string_literal", "_Sx"]),
+            desugar_expression(&json!("_Sx"))
+        );
+        assert_eq!(
+            json!(["string_literal", "_id"]),
+            desugar_expression(&json!("_id"))
+        );
+        assert_eq!(
+            json!(["string_literal", "_"]),
+            desugar_expression(&json!("_"))
+        );
+        assert_eq!(
+            json!(["parent", ["path", "a", ["source"]]]),
+            desugar_expression(&json!("_P._S.a"))
+        );
+        assert_eq!(
+            json!(["root", ["parent", ["target"]]]),
+            desugar_expression(&json!("_R._P._T"))
+        );
+        assert_eq!(
+            json!(["string_literal", "_P.a"]),
+            desugar_expression(&json!("_P.a"))
+        );
+    }
+
+    #[test]
+    fn test_desugar_literals() {
+        assert_eq!(
+            json!(["boolean_literal", true]),
+            desugar_expression(&json!(true))
+        );
+        assert_eq!(
+            json!(["transit_literal", "~rhttp://www.example.org/"]),
+            desugar_expression(&json!("~rhttp://www.example.org/"))
+        );
+        assert_eq!(
+            json!(["string_literal", "~r"]),
+            desugar_expression(&json!("~~r"))
+        );
+        assert_eq!(
+            json!([
+                "map",
+                ["upper", ["current"]],
+                ["list", ["string_literal", "a"]]
+            ]),
+            desugar_expression(&json!(["map", ["upper", "_."], ["list", "a"]]))
+        );
+    }
+}
@@ -1,64 +1,271 @@
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr};
 
-use crate::entity::EntityValue;
+use bigdecimal::{BigDecimal, One, RoundingMode, ToPrimitive, Zero};
+use regex::{Captures, Regex};
+use serde_json::Number;
+
+use crate::{
+    entity::{BigDecimalWrapper, Entity, EntityValue, MANAGED_PROPERTIES},
+    error::Error,
+};
 
 #[derive(Debug)]
 pub struct Target {
     target: EntityValue,
     filtered: bool,
+    discarded: bool,
     created_targets: Vec<EntityValue>,
+    errors: Vec<Error>,
 }
 
 impl Target {
     pub fn new() -> Self {
         Target {
-            target: EntityValue::Object(HashMap::new()),
+            target: EntityValue::Object(BTreeMap::new()),
             filtered: false,
+            discarded: false,
             created_targets: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, property_name: &str, value: EntityValue) {
+        if let EntityValue::Object(ref mut map) = self.target {
+            map.insert(property_name.into(), value);
         }
     }
 
-    pub fn add(&mut self, property_name: &'static str, value: EntityValue) {
-        match self.target {
-            EntityValue::Object(ref mut map) => {
-                map.insert(property_name.into(), value);
+    /// Copies the properties of the source entity selected by the patterns, see `matches_patterns`.
+    /// `_id` is always copied.
+    pub fn copy(
+        &mut self,
+        include: &[impl AsRef<str>],
+        exclude: &[impl AsRef<str>],
+        source: &EntityValue,
+    ) {
+        if let (EntityValue::Object(ref mut map), EntityValue::Object(source)) =
+            (&mut self.target, source)
+        {
+            for (name, value) in source {
+                if name == "_id" || matches_patterns(name, include, exclude) {
+                    map.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    /// Removes the properties selected by the patterns, `_id` is never removed.
+    pub fn remove(&mut self, include: &[impl AsRef<str>], exclude: &[impl AsRef<str>]) {
+        if let EntityValue::Object(ref mut map) = self.target {
+            map.retain(|name, _| name == "_id" || !matches_patterns(name, include, exclude));
+        }
+    }
+
+    /// Sets the properties of an object, or of every object in a list, on the
+    /// target. Nested objects replace what is there instead of being merged,
+    /// anything that isn't an object is ignored and `_id` is never merged.
+    pub fn merge(&mut self, value: EntityValue) {
+        self.merge_with(value, &|_, value| value);
+    }
+
+    /// Like `merge`, but when a property is already set the values are combined into
+    /// a list without duplicates, where lists are flattened and nulls left out.
+    pub fn merge_union(&mut self, value: EntityValue) {
+        self.merge_with(value, &|existing, value| {
+            let mut values = Vec::new();
+            for value in [existing, value] {
+                match value {
+                    EntityValue::Array(items) => values.extend(items),
+                    value => values.push(value),
+                }
+            }
+            let mut union: Vec<EntityValue> = Vec::with_capacity(values.len());
+            for value in values {
+                if !matches!(value, EntityValue::Null) && !union.contains(&value) {
+                    union.push(value);
+                }
+            }
+            match union.len() {
+                0 => EntityValue::Null,
+                1 => union.remove(0),
+                _ => EntityValue::Array(union),
+            }
+        });
+    }
+
+    fn merge_with(
+        &mut self,
+        value: EntityValue,
+        combine: &dyn Fn(EntityValue, EntityValue) -> EntityValue,
+    ) {
+        match value {
+            EntityValue::Array(items) => {
+                for item in items {
+                    self.merge_with(item, combine);
+                }
+            }
+            EntityValue::Object(properties) => {
+                if let EntityValue::Object(ref mut map) = self.target {
+                    for (name, value) in properties {
+                        if name == "_id" {
+                            continue;
+                        }
+                        let value = match map.remove(&name) {
+                            Some(existing) => combine(existing, value),
+                            None => value,
+                        };
+                        map.insert(name, value);
+                    }
+                }
             }
             _ => {}
         }
     }
 
+    /// Sets the property only if it is missing or null.
+    pub fn set_default(&mut self, property_name: &str, value: EntityValue) {
+        if let EntityValue::Object(ref mut map) = self.target {
+            match map.get(property_name) {
+                None | Some(EntityValue::Null) => {
+                    map.insert(property_name.into(), value);
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Moves a property to a new name, replacing any property already there.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let EntityValue::Object(ref mut map) = self.target {
+            if let Some(value) = map.remove(from) {
+                map.insert(to.into(), value);
+            }
+        }
+    }
+
+    pub fn entity(&self) -> &EntityValue {
+        &self.target
+    }
+
+    /// The created entities followed by the target, unless it was discarded.
+    /// Created entities get the `_id` of the target as `_parent`.
     pub fn output(&self) -> Vec<EntityValue> {
+        let parent = match &self.target {
+            EntityValue::Object(map) => map
+                .get("_id")
+                .filter(|id| matches!(id, EntityValue::String(_))),
+            _ => None,
+        };
         let mut output = self.created_targets.clone();
-        if !self.filtered {
-            output.push(self.target.clone());
+        if let Some(parent) = parent {
+            for entity in &mut output {
+                if let EntityValue::Object(map) = entity {
+                    map.entry("_parent".into())
+                        .or_insert_with(|| parent.clone());
+                }
+            }
+        }
+        if self.discarded {
+            return output;
         }
+        let mut target = self.target.clone();
+        if let (true, EntityValue::Object(ref mut map)) = (self.filtered, &mut target) {
+            map.insert("_filtered".into(), EntityValue::Bool(true));
+        }
+        output.push(target);
         output
     }
 
+    /// Marks the target `_filtered`, it is still output so that it can be deleted downstream.
     pub fn filter(&mut self) {
         self.filtered = true;
     }
 
+    /// Like `output`, but fails if anything passed to `create` wasn't an entity.
+    pub fn finish(self) -> Result<Vec<EntityValue>, Error> {
+        let output = self.output();
+        match self.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(output),
+        }
+    }
+
+    /// Like `finish`, but for a source entity: the target keeps the `_id` of the source
+    /// unless the rule set one, everything gets `_deleted` if the source is deleted, and
+    /// the properties managed by whoever stores the entities are left out, apart from
+    /// `_hash` which is computed from the content.
+    pub fn into_entities(mut self, source: &Entity) -> Result<Vec<Entity>, Error> {
+        self.set_default("_id", EntityValue::String(source.id.clone()));
+        self.finish()?
+            .into_iter()
+            .map(|mut value| {
+                if let EntityValue::Object(map) = &mut value {
+                    map.retain(|name, _| !MANAGED_PROPERTIES.contains(&name.as_str()));
+                }
+                let mut entity = Entity::try_from(value)?;
+                entity.deleted |= source.deleted;
+                entity.update_hash();
+                Ok(entity)
+            })
+            .collect()
+    }
+
+    /// Leaves the target out of the output completely.
+    pub fn discard(&mut self) {
+        self.discarded = true;
+    }
+
+    /// Creates an entity, or one for every item in a list. Entities must be objects
+    /// with a string `_id`, anything else is left out and reported by `finish`.
     pub fn create(&mut self, value: EntityValue) {
         match value {
-            EntityValue::Array(arr) => self.created_targets.extend(arr),
-            v => self.created_targets.push(v),
+            EntityValue::Array(arr) => arr.into_iter().for_each(|v| self.create_entity(v)),
+            v => self.create_entity(v),
+        }
+    }
+
+    fn create_entity(&mut self, entity: EntityValue) {
+        let invalid = match &entity {
+            EntityValue::Object(map) => match map.get("_id") {
+                Some(EntityValue::String(_)) => None,
+                Some(_) => Some("'_id' must be a string"),
+                None => Some("created entities must have an '_id'"),
+            },
+            _ => Some("created entities must be objects"),
+        };
+        match invalid {
+            Some(reason) => self.errors.push(Error::InvalidEntity(entity, reason)),
+            None => self.created_targets.push(entity),
         }
     }
 }
 
+impl Default for Target {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn string_helper(source: &EntityValue, function: impl Fn(&String) -> String) -> EntityValue {
+    string_value_helper(source, |s| EntityValue::String(function(s)))
+}
+
+/// Calls the function for a string, or for every string in a list, other values are dropped.
+fn string_value_helper(
+    source: &EntityValue,
+    function: impl Fn(&String) -> EntityValue,
+) -> EntityValue {
     match source {
         EntityValue::Array(arr) => EntityValue::Array(
-            arr.into_iter()
+            arr.iter()
                 .filter_map(|s| match s {
-                    EntityValue::String(s) => Some(EntityValue::String(function(s))),
+                    EntityValue::String(s) => Some(function(s)),
                     _ => None,
                 })
                 .collect(),
         ),
-        EntityValue::String(s) => EntityValue::String(function(s)),
-        _ => return EntityValue::Array(vec![]),
+        EntityValue::String(s) => function(s),
+        _ => EntityValue::Array(vec![]),
     }
 }
 
@@ -70,6 +277,723 @@ pub fn upper(source: &EntityValue) -> EntityValue {
     string_helper(source, |s| s.to_uppercase())
 }
 
+/// Removes leading and trailing whitespace.
+pub fn trim(source: &EntityValue) -> EntityValue {
+    string_helper(source, |s| s.trim().to_owned())
+}
+
+/// Removes leading and trailing characters that are in `chars`, whitespace if it is null.
+pub fn strip(chars: &EntityValue, source: &EntityValue) -> EntityValue {
+    strip_helper(chars, source, |s, strip| s.trim_matches(strip).to_owned())
+}
+
+pub fn lstrip(chars: &EntityValue, source: &EntityValue) -> EntityValue {
+    strip_helper(chars, source, |s, strip| {
+        s.trim_start_matches(strip).to_owned()
+    })
+}
+
+pub fn rstrip(chars: &EntityValue, source: &EntityValue) -> EntityValue {
+    strip_helper(chars, source, |s, strip| {
+        s.trim_end_matches(strip).to_owned()
+    })
+}
+
+fn strip_helper(
+    chars: &EntityValue,
+    source: &EntityValue,
+    function: impl Fn(&str, &dyn Fn(char) -> bool) -> String,
+) -> EntityValue {
+    match chars {
+        EntityValue::String(chars) => {
+            string_helper(source, |s| function(s, &|c| chars.contains(c)))
+        }
+        _ => string_helper(source, |s| function(s, &char::is_whitespace)),
+    }
+}
+
+/// Replaces every occurrence of `from` with `to`, nothing is replaced unless both are strings.
+pub fn replace(from: &EntityValue, to: &EntityValue, source: &EntityValue) -> EntityValue {
+    match (from, to) {
+        (EntityValue::String(from), EntityValue::String(to)) if !from.is_empty() => {
+            string_helper(source, |s| s.replace(from.as_str(), to))
+        }
+        _ => string_helper(source, |s| s.clone()),
+    }
+}
+
+/// The characters from `start` up to `end`, negative indexes count from the end of the string.
+pub fn substring(start: &EntityValue, end: &EntityValue, source: &EntityValue) -> EntityValue {
+    let (start, end) = (as_index(start), as_index(end));
+    string_helper(source, |s| {
+        let len = s.chars().count() as i64;
+        let resolve = |index: i64| {
+            if index < 0 {
+                (len + index).max(0)
+            } else {
+                index.min(len)
+            }
+        };
+        let start = start.map_or(0, resolve);
+        let end = end.map_or(len, resolve);
+        s.chars()
+            .skip(start as usize)
+            .take((end - start).max(0) as usize)
+            .collect()
+    })
+}
+
+fn as_index(value: &EntityValue) -> Option<i64> {
+    match value {
+        EntityValue::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
+
+/// Splits strings by a separator, splitting a list gives the parts of all its strings.
+pub fn split(separator: &EntityValue, source: &EntityValue) -> EntityValue {
+    let EntityValue::String(separator) = separator else {
+        return EntityValue::Array(vec![]);
+    };
+    let parts = |s: &String| {
+        if separator.is_empty() {
+            s.chars()
+                .map(|c| EntityValue::String(c.to_string()))
+                .collect()
+        } else {
+            s.split(separator.as_str())
+                .map(|part| EntityValue::String(part.to_owned()))
+                .collect()
+        }
+    };
+    match source {
+        EntityValue::Array(items) => EntityValue::Array(
+            items
+                .iter()
+                .flat_map(|item| match item {
+                    EntityValue::String(s) => parts(s),
+                    _ => vec![],
+                })
+                .collect(),
+        ),
+        EntityValue::String(s) => EntityValue::Array(parts(s)),
+        _ => EntityValue::Array(vec![]),
+    }
+}
+
+/// Joins the strings of a list with a separator, like `concat` other values are skipped.
+pub fn join(separator: &EntityValue, source: &EntityValue) -> EntityValue {
+    let separator = match separator {
+        EntityValue::String(separator) => separator.as_str(),
+        _ => "",
+    };
+    match source {
+        EntityValue::Array(items) => EntityValue::String(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    EntityValue::String(s) => Some(s.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(separator),
+        ),
+        EntityValue::String(s) => EntityValue::String(s.clone()),
+        _ => EntityValue::Null,
+    }
+}
+
+/// The number of characters in a string, items in a list or properties in an object.
+pub fn length(source: &EntityValue) -> EntityValue {
+    let length = match source {
+        EntityValue::String(s) => s.chars().count(),
+        EntityValue::Array(items) => items.len(),
+        EntityValue::Object(map) => map.len(),
+        _ => return EntityValue::Null,
+    };
+    EntityValue::Number(length.into())
+}
+
+pub fn startswith(prefix: &EntityValue, source: &EntityValue) -> EntityValue {
+    match prefix {
+        EntityValue::String(prefix) => string_value_helper(source, |s| {
+            EntityValue::Bool(s.starts_with(prefix.as_str()))
+        }),
+        _ => string_value_helper(source, |_| EntityValue::Bool(false)),
+    }
+}
+
+pub fn endswith(suffix: &EntityValue, source: &EntityValue) -> EntityValue {
+    match suffix {
+        EntityValue::String(suffix) => {
+            string_value_helper(source, |s| EntityValue::Bool(s.ends_with(suffix.as_str())))
+        }
+        _ => string_value_helper(source, |_| EntityValue::Bool(false)),
+    }
+}
+
+/// Pads strings on the right to `width` characters, with `fill` or spaces.
+pub fn ljust(width: &EntityValue, fill: &EntityValue, source: &EntityValue) -> EntityValue {
+    justify_helper(width, fill, source, |s, padding| {
+        format!("{}{}", s, padding)
+    })
+}
+
+/// Pads strings on the left to `width` characters, with `fill` or spaces.
+pub fn rjust(width: &EntityValue, fill: &EntityValue, source: &EntityValue) -> EntityValue {
+    justify_helper(width, fill, source, |s, padding| {
+        format!("{}{}", padding, s)
+    })
+}
+
+/// The widest `ljust` and `rjust` pad to, strings are left as they are for wider widths.
+const MAX_JUSTIFY_WIDTH: i64 = 1 << 20;
+
+fn justify_helper(
+    width: &EntityValue,
+    fill: &EntityValue,
+    source: &EntityValue,
+    function: impl Fn(&str, String) -> String,
+) -> EntityValue {
+    let width = match as_index(width).unwrap_or(0) {
+        width if width > MAX_JUSTIFY_WIDTH => 0,
+        width => width.max(0) as usize,
+    };
+    let fill = match fill {
+        EntityValue::String(fill) => fill.chars().next().unwrap_or(' '),
+        _ => ' ',
+    };
+    string_helper(source, |s| {
+        let padding = width.saturating_sub(s.chars().count());
+        function(s, std::iter::repeat_n(fill, padding).collect())
+    })
+}
+
+/// A function that gives the value for what a coercion function can't convert, it gets the
+/// value as `_.` when it is an expression.
+pub type DefaultValue<'a> = Option<&'a dyn Fn(&EntityValue) -> EntityValue>;
+
+/// Converts a value or every item in a list. What `convert` can't convert is replaced by the
+/// default, or without one is null and left out of lists.
+fn coerce_helper(
+    default: DefaultValue,
+    source: &EntityValue,
+    convert: impl Fn(&EntityValue) -> Option<EntityValue>,
+) -> EntityValue {
+    let coerce = |value: &EntityValue| convert(value).or_else(|| default.map(|d| d(value)));
+    match source {
+        EntityValue::Array(items) => EntityValue::Array(items.iter().filter_map(coerce).collect()),
+        value => coerce(value).unwrap_or(EntityValue::Null),
+    }
+}
+
+/// Converts booleans and the strings "true" and "false", in any case, to booleans.
+pub fn boolean(default: DefaultValue, source: &EntityValue) -> EntityValue {
+    coerce_helper(default, source, |value| match value {
+        EntityValue::Bool(b) => Some(EntityValue::Bool(*b)),
+        EntityValue::String(s) if s.eq_ignore_ascii_case("true") => Some(EntityValue::Bool(true)),
+        EntityValue::String(s) if s.eq_ignore_ascii_case("false") => Some(EntityValue::Bool(false)),
+        _ => None,
+    })
+}
+
+/// Converts numbers, decimals and strings of integers to integers, fractions are cut off.
+pub fn integer(default: DefaultValue, source: &EntityValue) -> EntityValue {
+    coerce_helper(default, source, |value| {
+        let integer = match value {
+            EntityValue::Number(n) if n.is_i64() || n.is_u64() => {
+                return Some(EntityValue::Number(n.clone()))
+            }
+            EntityValue::Number(n) => n
+                .as_f64()
+                .filter(|f| (i64::MIN as f64..=i64::MAX as f64).contains(f))
+                .map(|f| f as i64),
+            EntityValue::Decimal(decimal) => decimal.to_i64(),
+            EntityValue::String(s) => s.parse().ok(),
+            _ => None,
+        };
+        integer.map(|i: i64| EntityValue::Number(i.into()))
+    })
+}
+
+/// Converts numbers, decimals and strings of numbers to floating point numbers.
+pub fn float(default: DefaultValue, source: &EntityValue) -> EntityValue {
+    coerce_helper(default, source, |value| {
+        let float = match value {
+            EntityValue::Number(n) => n.as_f64(),
+            EntityValue::Decimal(decimal) => decimal.to_f64(),
+            EntityValue::String(s) => s.parse().ok(),
+            _ => None,
+        };
+        float
+            .and_then(serde_json::Number::from_f64)
+            .map(EntityValue::Number)
+    })
+}
+
+/// Converts numbers and strings of numbers to decimals.
+pub fn decimal(default: DefaultValue, source: &EntityValue) -> EntityValue {
+    coerce_helper(default, source, |value| {
+        let decimal = match value {
+            EntityValue::Decimal(_) => return Some(value.clone()),
+            EntityValue::Number(n) => BigDecimalWrapper::parse(&n.to_string()),
+            EntityValue::String(s) => BigDecimalWrapper::parse(s),
+            _ => return None,
+        };
+        decimal.ok().map(EntityValue::Decimal)
+    })
+}
+
+/// Converts values to strings, transit values lose their prefix except for NIs. Only null
+/// can't be converted.
+pub fn string(default: DefaultValue, source: &EntityValue) -> EntityValue {
+    coerce_helper(default, source, |value| match value {
+        EntityValue::Null => None,
+        value => Some(to_string(value)),
+    })
+}
+
+fn to_string(value: &EntityValue) -> EntityValue {
+    let s = match value {
+        EntityValue::String(s) => s.clone(),
+        EntityValue::Number(n) => n.to_string(),
+        EntityValue::Bool(b) => b.to_string(),
+        EntityValue::NI(ni) => ni.to_string(),
+        EntityValue::URI(uri) => uri.to_string()[2..].to_owned(),
+        EntityValue::Date(date) => date.to_string()[2..].to_owned(),
+        EntityValue::DateTime(date_time) => date_time.to_string()[2..].to_owned(),
+        EntityValue::UUID(uuid) => uuid.to_string()[2..].to_owned(),
+        EntityValue::Bytes(bytes) => bytes.to_string()[2..].to_owned(),
+        EntityValue::Decimal(decimal) => decimal.to_string()[2..].to_owned(),
+        value => serde_json::to_string(value).unwrap_or_default(),
+    };
+    EntityValue::String(s)
+}
+
+/// Whether strings match a glob pattern, where `*` matches anything and `?` one character.
+pub fn matches(pattern: &EntityValue, source: &EntityValue) -> EntityValue {
+    match pattern {
+        EntityValue::String(pattern) => {
+            string_value_helper(source, |s| EntityValue::Bool(glob_match(pattern, s)))
+        }
+        _ => string_value_helper(source, |_| EntityValue::Bool(false)),
+    }
+}
+
+/// Extracts the first match of a regular expression from strings, or all of them into a list
+/// if `all` is true. Strings that don't match are null, and are left out of lists.
+pub fn regex(pattern: &Regex, all: &EntityValue, source: &EntityValue) -> EntityValue {
+    let all = is_true(all);
+    let extract = |s: &str| -> Vec<EntityValue> {
+        if all {
+            pattern.captures_iter(s).map(|c| groups(&c)).collect()
+        } else {
+            pattern
+                .captures(s)
+                .map(|c| groups(&c))
+                .into_iter()
+                .collect()
+        }
+    };
+    match source {
+        EntityValue::Array(items) => EntityValue::Array(
+            items
+                .iter()
+                .flat_map(|item| match item {
+                    EntityValue::String(s) => extract(s),
+                    _ => vec![],
+                })
+                .collect(),
+        ),
+        EntityValue::String(s) if all => EntityValue::Array(extract(s)),
+        EntityValue::String(s) => extract(s).pop().unwrap_or(EntityValue::Null),
+        _ => EntityValue::Array(vec![]),
+    }
+}
+
+/// The whole match if the pattern has no groups, the group if it has one, otherwise a list
+/// of the groups, where groups that took no part in the match are null.
+fn groups(captures: &Captures) -> EntityValue {
+    let group = |m: Option<regex::Match>| {
+        m.map_or(EntityValue::Null, |m| {
+            EntityValue::String(m.as_str().to_owned())
+        })
+    };
+    match captures.len() {
+        1 => group(captures.get(0)),
+        2 => group(captures.get(1)),
+        _ => EntityValue::Array(captures.iter().skip(1).map(group).collect()),
+    }
+}
+
+/// Replaces every match of a regular expression, `$1` or `${name}` in the replacement is
+/// the text of a group.
+pub fn regex_sub(pattern: &Regex, replacement: &EntityValue, source: &EntityValue) -> EntityValue {
+    match replacement {
+        EntityValue::String(replacement) => string_helper(source, |s| {
+            pattern.replace_all(s, replacement.as_str()).into_owned()
+        }),
+        _ => string_helper(source, |s| s.clone()),
+    }
+}
+
+/// A number in the representation arithmetic is done in. Integers are promoted to floats
+/// when mixed with them, and both to decimals when mixed with decimals.
+#[derive(Debug, Clone)]
+enum Numeric {
+    Integer(i64),
+    Float(f64),
+    Decimal(BigDecimal),
+}
+
+impl Numeric {
+    fn of(value: &EntityValue) -> Option<Numeric> {
+        match value {
+            EntityValue::Number(n) => n
+                .as_i64()
+                .map(Numeric::Integer)
+                // integers above i64::MAX are kept exact
+                .or_else(|| n.as_u64().map(|n| Numeric::Decimal(n.into())))
+                .or_else(|| n.as_f64().map(Numeric::Float)),
+            EntityValue::Decimal(decimal) => {
+                Some(Numeric::Decimal(decimal.as_big_decimal().clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> EntityValue {
+        match self {
+            Numeric::Integer(i) => EntityValue::Number(i.into()),
+            Numeric::Float(f) => Number::from_f64(f).map_or(EntityValue::Null, EntityValue::Number),
+            Numeric::Decimal(d) => EntityValue::Decimal(d.into()),
+        }
+    }
+
+    /// Floats are converted from their shortest representation, so 0.1 stays 0.1.
+    fn to_decimal(&self) -> Option<BigDecimal> {
+        match self {
+            Numeric::Integer(i) => Some((*i).into()),
+            Numeric::Float(f) => BigDecimal::from_str(&f.to_string()).ok(),
+            Numeric::Decimal(d) => Some(d.clone()),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Numeric::Integer(i) => *i as f64,
+            Numeric::Float(f) => *f,
+            Numeric::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    fn float(f: f64) -> Option<Numeric> {
+        f.is_finite().then_some(Numeric::Float(f))
+    }
+
+    /// Floats that are whole numbers become integers, if they fit.
+    fn integral(f: f64) -> Option<Numeric> {
+        if (i64::MIN as f64..i64::MAX as f64).contains(&f) {
+            Some(Numeric::Integer(f as i64))
+        } else {
+            Numeric::float(f)
+        }
+    }
+
+    fn compare(&self, other: &Numeric) -> Option<Ordering> {
+        match (self, other) {
+            (Numeric::Integer(a), Numeric::Integer(b)) => Some(a.cmp(b)),
+            (Numeric::Decimal(_), _) | (_, Numeric::Decimal(_)) => {
+                Some(self.to_decimal()?.cmp(&other.to_decimal()?))
+            }
+            (a, b) => a.to_f64().partial_cmp(&b.to_f64()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Mod,
+    Pow,
+}
+
+/// The largest integer exponent `pow` computes decimals for, and the most digits `round`
+/// rounds to.
+const MAX_DECIMAL_EXPONENT: u64 = 1024;
+
+/// Operates in the widest representation of the two numbers. Integer operations that
+/// overflow are done on decimals instead, and what can't be computed, like dividing by
+/// zero, gives `None`.
+fn operate(operation: Operation, a: &Numeric, b: &Numeric) -> Option<Numeric> {
+    match (a, b) {
+        (Numeric::Integer(x), Numeric::Integer(y)) => integer_operation(operation, *x, *y)
+            .or_else(|| decimal_operation(operation, (*x).into(), (*y).into())),
+        (Numeric::Decimal(_), _) | (_, Numeric::Decimal(_)) => {
+            decimal_operation(operation, a.to_decimal()?, b.to_decimal()?)
+        }
+        (a, b) => float_operation(operation, a.to_f64(), b.to_f64()),
+    }
+}
+
+/// Dividing integers that don't divide evenly and negative exponents give floats.
+fn integer_operation(operation: Operation, x: i64, y: i64) -> Option<Numeric> {
+    match operation {
+        Operation::Plus => x.checked_add(y).map(Numeric::Integer),
+        Operation::Minus => x.checked_sub(y).map(Numeric::Integer),
+        Operation::Multiply => x.checked_mul(y).map(Numeric::Integer),
+        Operation::Divide => match x.checked_rem(y)? {
+            0 => x.checked_div(y).map(Numeric::Integer),
+            _ => Numeric::float(x as f64 / y as f64),
+        },
+        Operation::Mod => x
+            .checked_rem(y)
+            .map(|r| Numeric::Integer(floor_remainder(r, y, 0))),
+        Operation::Pow if y >= 0 => u32::try_from(y)
+            .ok()
+            .and_then(|y| x.checked_pow(y))
+            .map(Numeric::Integer),
+        Operation::Pow => Numeric::float((x as f64).powf(y as f64)),
+    }
+}
+
+fn float_operation(operation: Operation, x: f64, y: f64) -> Option<Numeric> {
+    let result = match operation {
+        Operation::Plus => x + y,
+        Operation::Minus => x - y,
+        Operation::Multiply => x * y,
+        Operation::Divide if y != 0.0 => x / y,
+        Operation::Mod if y != 0.0 => floor_remainder(x % y, y, 0.0),
+        Operation::Divide | Operation::Mod => return None,
+        Operation::Pow => x.powf(y),
+    };
+    Numeric::float(result)
+}
+
+fn decimal_operation(operation: Operation, x: BigDecimal, y: BigDecimal) -> Option<Numeric> {
+    let result = match operation {
+        Operation::Plus => x + y,
+        Operation::Minus => x - y,
+        Operation::Multiply => x * y,
+        Operation::Divide if !y.is_zero() => x / y,
+        Operation::Mod if !y.is_zero() => floor_remainder(&x % &y, y, BigDecimal::zero()),
+        Operation::Divide | Operation::Mod => return None,
+        Operation::Pow => return decimal_pow(x, y),
+    };
+    Some(Numeric::Decimal(result))
+}
+
+/// Decimals are raised to integer exponents exactly, other exponents give floats.
+fn decimal_pow(x: BigDecimal, y: BigDecimal) -> Option<Numeric> {
+    if !y.is_integer() {
+        return float_operation(Operation::Pow, x.to_f64()?, y.to_f64()?);
+    }
+    // the powers of 0, 1 and -1 don't grow, whatever the exponent
+    if x.is_zero() {
+        return match y.cmp(&BigDecimal::zero()) {
+            Ordering::Less => None,
+            Ordering::Equal => Some(Numeric::Decimal(BigDecimal::one())),
+            Ordering::Greater => Some(Numeric::Decimal(x)),
+        };
+    }
+    if x.abs().is_one() {
+        let even = (&y % BigDecimal::from(2)).is_zero();
+        return Some(Numeric::Decimal(if even { x.abs() } else { x }));
+    }
+    let exponent = y.to_i64()?;
+    if exponent.unsigned_abs() > MAX_DECIMAL_EXPONENT {
+        return None;
+    }
+    let (mut base, mut remaining) = (x, exponent.unsigned_abs());
+    let mut result = BigDecimal::one();
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            result = &result * &base;
+        }
+        base = &base * &base;
+        remaining >>= 1;
+    }
+    if exponent >= 0 {
+        Some(Numeric::Decimal(result))
+    } else if result.is_zero() {
+        None
+    } else {
+        Some(Numeric::Decimal(result.inverse()))
+    }
+}
+
+/// Gives the remainder the sign of the divisor, so -7 mod 3 is 2 as in Python.
+fn floor_remainder<T: PartialOrd + std::ops::Add<Output = T>>(r: T, y: T, zero: T) -> T {
+    if r != zero && (r < zero) != (y < zero) {
+        r + y
+    } else {
+        r
+    }
+}
+
+/// Applies an operation to two numbers, a number and every number in a list, or the numbers
+/// at the same place in two lists. Values that aren't numbers give null, and are left out of
+/// lists.
+fn arithmetic_helper(operation: Operation, a: &EntityValue, b: &EntityValue) -> EntityValue {
+    let apply = |a: &EntityValue, b: &EntityValue| {
+        operate(operation, &Numeric::of(a)?, &Numeric::of(b)?).map(Numeric::into_value)
+    };
+    match (a, b) {
+        (EntityValue::Array(a), EntityValue::Array(b)) => {
+            EntityValue::Array(a.iter().zip(b).filter_map(|(a, b)| apply(a, b)).collect())
+        }
+        (EntityValue::Array(a), b) => {
+            EntityValue::Array(a.iter().filter_map(|a| apply(a, b)).collect())
+        }
+        (a, EntityValue::Array(b)) => {
+            EntityValue::Array(b.iter().filter_map(|b| apply(a, b)).collect())
+        }
+        (a, b) => apply(a, b).unwrap_or(EntityValue::Null),
+    }
+}
+
+/// Applies a function to a number or every number in a list, like `arithmetic_helper`.
+fn numeric_helper(
+    source: &EntityValue,
+    function: impl Fn(Numeric) -> Option<Numeric>,
+) -> EntityValue {
+    let apply = |value: &EntityValue| function(Numeric::of(value)?).map(Numeric::into_value);
+    match source {
+        EntityValue::Array(items) => EntityValue::Array(items.iter().filter_map(apply).collect()),
+        value => apply(value).unwrap_or(EntityValue::Null),
+    }
+}
+
+pub fn plus(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    arithmetic_helper(Operation::Plus, a, b)
+}
+
+pub fn minus(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    arithmetic_helper(Operation::Minus, a, b)
+}
+
+pub fn multiply(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    arithmetic_helper(Operation::Multiply, a, b)
+}
+
+/// Dividing integers gives an integer if they divide evenly, a float otherwise. Dividing by
+/// zero gives null.
+pub fn divide(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    arithmetic_helper(Operation::Divide, a, b)
+}
+
+/// The remainder has the sign of the divisor, and is null for zero.
+pub fn modulo(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    arithmetic_helper(Operation::Mod, a, b)
+}
+
+pub fn pow(base: &EntityValue, exponent: &EntityValue) -> EntityValue {
+    arithmetic_helper(Operation::Pow, base, exponent)
+}
+
+pub fn abs(source: &EntityValue) -> EntityValue {
+    numeric_helper(source, |n| match n {
+        Numeric::Integer(i) => i
+            .checked_abs()
+            .map(Numeric::Integer)
+            .or_else(|| Some(Numeric::Decimal(BigDecimal::from(i).abs()))),
+        Numeric::Float(f) => Some(Numeric::Float(f.abs())),
+        Numeric::Decimal(d) => Some(Numeric::Decimal(d.abs())),
+    })
+}
+
+pub fn floor(source: &EntityValue) -> EntityValue {
+    numeric_helper(source, |n| match n {
+        Numeric::Float(f) => Numeric::integral(f.floor()),
+        Numeric::Decimal(d) => Some(Numeric::Decimal(d.with_scale_round(0, RoundingMode::Floor))),
+        integer => Some(integer),
+    })
+}
+
+pub fn ceil(source: &EntityValue) -> EntityValue {
+    numeric_helper(source, |n| match n {
+        Numeric::Float(f) => Numeric::integral(f.ceil()),
+        Numeric::Decimal(d) => Some(Numeric::Decimal(
+            d.with_scale_round(0, RoundingMode::Ceiling),
+        )),
+        integer => Some(integer),
+    })
+}
+
+/// Rounds to `digits` decimals, 0 if it is null, with halves rounded away from zero. Floats
+/// rounded to whole numbers become integers, a negative number of digits rounds to tens,
+/// hundreds and so on. Rounding to more digits either way than `MAX_DECIMAL_EXPONENT` gives
+/// null, except for integers that `digits` leaves as they are.
+pub fn round(digits: &EntityValue, source: &EntityValue) -> EntityValue {
+    let digits = match digits {
+        EntityValue::Null => 0,
+        digits => match as_index(digits) {
+            Some(digits) => digits,
+            None => return numeric_helper(source, |_| None),
+        },
+    };
+    let round = |d: &BigDecimal| {
+        (digits.unsigned_abs() <= MAX_DECIMAL_EXPONENT)
+            .then(|| d.with_scale_round(digits, RoundingMode::HalfUp))
+    };
+    numeric_helper(source, |n| match n {
+        Numeric::Integer(_) if digits >= 0 => Some(n),
+        Numeric::Integer(i) => round(&i.into())?.to_i64().map(Numeric::Integer),
+        Numeric::Float(f) if digits == 0 => Numeric::integral(f.round()),
+        Numeric::Float(_) if digits < 0 => Numeric::integral(round(&n.to_decimal()?)?.to_f64()?),
+        Numeric::Float(_) => Numeric::float(round(&n.to_decimal()?)?.to_f64()?),
+        Numeric::Decimal(d) => round(&d).map(Numeric::Decimal),
+    })
+}
+
+/// The sum of the numbers in a list, values that aren't numbers are skipped.
+pub fn sum(values: &EntityValue) -> EntityValue {
+    numbers(values)
+        .iter()
+        .try_fold(Numeric::Integer(0), |sum, n| {
+            operate(Operation::Plus, &sum, n)
+        })
+        .map_or(EntityValue::Null, Numeric::into_value)
+}
+
+/// The smallest number in a list, null if there are none.
+pub fn min(values: &EntityValue) -> EntityValue {
+    extreme(values, Ordering::Less)
+}
+
+/// The largest number in a list, null if there are none.
+pub fn max(values: &EntityValue) -> EntityValue {
+    extreme(values, Ordering::Greater)
+}
+
+fn extreme(values: &EntityValue, wanted: Ordering) -> EntityValue {
+    let items = match values {
+        EntityValue::Array(items) => items.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    let mut extreme: Option<(&EntityValue, Numeric)> = None;
+    for item in items {
+        let Some(n) = Numeric::of(item) else {
+            continue;
+        };
+        match &extreme {
+            Some((_, current)) if n.compare(current) != Some(wanted) => {}
+            _ => extreme = Some((item, n)),
+        }
+    }
+    extreme.map_or(EntityValue::Null, |(item, _)| item.clone())
+}
+
+fn numbers(values: &EntityValue) -> Vec<Numeric> {
+    match values {
+        EntityValue::Array(items) => items.iter().filter_map(Numeric::of).collect(),
+        value => Numeric::of(value).into_iter().collect(),
+    }
+}
+
 pub fn list_literal(content: &[EntityValue]) -> EntityValue {
     EntityValue::Array(content.to_vec())
 }
@@ -102,14 +1026,47 @@ pub fn concat(parts: &EntityValue) -> EntityValue {
     }
 }
 
+/// Sets the `_id` of an object, or of every object in a list, to what the function returns for it.
+pub fn set_ids(value: EntityValue, id: impl Fn(&EntityValue) -> EntityValue) -> EntityValue {
+    set_ids_with(value, &id)
+}
+
+fn set_ids_with(value: EntityValue, id: &dyn Fn(&EntityValue) -> EntityValue) -> EntityValue {
+    match value {
+        EntityValue::Array(items) => EntityValue::Array(
+            items
+                .into_iter()
+                .map(|item| set_ids_with(item, id))
+                .collect(),
+        ),
+        EntityValue::Object(mut map) => {
+            let id = id(&EntityValue::Object(map.clone()));
+            map.insert("_id".into(), id);
+            EntityValue::Object(map)
+        }
+        value => value,
+    }
+}
+
+/// Runs a rule for an object, or every object in a list. Filtered entities are
+/// left out, they only track deletions when they are output by the default rule.
 pub fn apply(
     function: impl Fn(&EntityValue) -> Vec<EntityValue>,
     items: &EntityValue,
 ) -> EntityValue {
+    let function = |item: &EntityValue| {
+        let mut output = function(item);
+        output.retain(|entity| !is_filtered(entity));
+        output
+    };
     match items {
         EntityValue::Array(arr) => {
-            EntityValue::Array(arr.iter().flat_map(|v| function(v)).collect())
+            EntityValue::Array(arr.iter().flat_map(function).collect())
         }
+        EntityValue::Object(_) => match function(items).as_slice() {
+            [single] => single.clone(),
+            many => EntityValue::Array(many.to_vec()),
+        },
         _ => EntityValue::Array(vec![]),
     }
 }
@@ -117,28 +1074,161 @@ pub fn apply(
 pub fn map(function: impl Fn(&EntityValue) -> EntityValue, items: &EntityValue) -> EntityValue {
     match items {
         EntityValue::Array(arr) => {
-            EntityValue::Array(arr.iter().map(|item| function(item)).collect())
+            EntityValue::Array(arr.iter().map(function).collect())
         }
         _ => EntityValue::Null,
     }
 }
 
-pub fn path<'a>(arg: EntityValue, value: &'a EntityValue) -> &'a EntityValue {
-    fn eval_path<'a>(arg: &[&str], value: &'a EntityValue) -> &'a EntityValue {
-        if arg.is_empty() {
-            return value;
-        }
-        let (first, rest) = arg.split_first().unwrap();
-        if let EntityValue::Object(map) = value {
-            if let Some(v) = map.get(*first) {
-                return eval_path(rest, v);
+pub fn is_filtered(entity: &EntityValue) -> bool {
+    match entity {
+        EntityValue::Object(map) => matches!(map.get("_filtered"), Some(EntityValue::Bool(true))),
+        _ => false,
+    }
+}
+
+/// Whether two values are the same, see `EntityValue::compare`.
+pub fn eq(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    EntityValue::Bool(a.compare(b).is_eq())
+}
+
+pub fn neq(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    EntityValue::Bool(a.compare(b).is_ne())
+}
+
+pub fn gt(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    EntityValue::Bool(a.compare(b).is_gt())
+}
+
+pub fn gte(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    EntityValue::Bool(a.compare(b).is_ge())
+}
+
+pub fn lt(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    EntityValue::Bool(a.compare(b).is_lt())
+}
+
+pub fn lte(a: &EntityValue, b: &EntityValue) -> EntityValue {
+    EntityValue::Bool(a.compare(b).is_le())
+}
+
+/// Whether all the values are true, see `is_true`.
+pub fn and(values: &[EntityValue]) -> EntityValue {
+    EntityValue::Bool(values.iter().all(is_true))
+}
+
+/// Whether any of the values is true, see `is_true`.
+pub fn or(values: &[EntityValue]) -> EntityValue {
+    EntityValue::Bool(values.iter().any(is_true))
+}
+
+pub fn not(value: &EntityValue) -> EntityValue {
+    EntityValue::Bool(!is_true(value))
+}
+
+/// Whether a value is in a list, or is the same as a value that isn't a list.
+pub fn is_in(value: &EntityValue, values: &EntityValue) -> EntityValue {
+    let found = match values {
+        EntityValue::Array(items) => items.iter().any(|item| value.compare(item).is_eq()),
+        other => value.compare(other).is_eq(),
+    };
+    EntityValue::Bool(found)
+}
+
+pub fn is_null(value: &EntityValue) -> EntityValue {
+    EntityValue::Bool(matches!(value, EntityValue::Null))
+}
+
+pub fn is_empty(value: &EntityValue) -> EntityValue {
+    EntityValue::Bool(empty(value))
+}
+
+pub fn is_not_empty(value: &EntityValue) -> EntityValue {
+    EntityValue::Bool(!empty(value))
+}
+
+/// Null, and empty lists, strings and objects are empty.
+fn empty(value: &EntityValue) -> bool {
+    match value {
+        EntityValue::Null => true,
+        EntityValue::Array(items) => items.is_empty(),
+        EntityValue::String(s) => s.is_empty(),
+        EntityValue::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Whether a condition holds, which is when it is true or a list containing true.
+pub fn is_true(value: &EntityValue) -> bool {
+    match value {
+        EntityValue::Bool(b) => *b,
+        EntityValue::Array(items) => items
+            .iter()
+            .any(|item| matches!(item, EntityValue::Bool(true))),
+        _ => false,
+    }
+}
+
+/// Checks that a property matches one of the include patterns and none of the
+/// exclude patterns. Wildcards don't match properties starting with `_`, those
+/// are only matched by patterns that start with `_` too, e.g. `_deleted` or `_*`.
+pub fn matches_patterns(
+    name: &str,
+    include: &[impl AsRef<str>],
+    exclude: &[impl AsRef<str>],
+) -> bool {
+    let matches = |pattern: &str| {
+        (!name.starts_with('_') || pattern.starts_with('_')) && glob_match(pattern, name)
+    };
+    include.iter().any(|p| matches(p.as_ref())) && !exclude.iter().any(|p| matches(p.as_ref()))
+}
+
+/// Matches a glob pattern, where `*` matches any number of characters and `?` a single one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where to resume if the characters after the last star stop matching
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
             }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
         }
-        &EntityValue::Null
     }
+    pattern[p..].iter().all(|c| *c == '*')
+}
 
+/// Follows already split path segments, for when the path is known up front.
+pub fn path_segments<'a>(segments: &[impl AsRef<str>], value: &'a EntityValue) -> &'a EntityValue {
+    let Some((first, rest)) = segments.split_first() else {
+        return value;
+    };
+    if let EntityValue::Object(map) = value {
+        if let Some(v) = map.get(first.as_ref()) {
+            return path_segments(rest, v);
+        }
+    }
+    &EntityValue::Null
+}
+
+pub fn path(arg: EntityValue, value: &EntityValue) -> &EntityValue {
     match arg {
-        EntityValue::String(s) => eval_path(&[&s], value),
+        EntityValue::String(s) => path_segments(&[s.as_str()], value),
         EntityValue::Array(arr) => {
             let paths: Vec<&str> = arr
                 .iter()
@@ -150,7 +1240,7 @@ pub fn path<'a>(arg: EntityValue, value: &'a EntityValue) -> &'a EntityValue {
                     }
                 })
                 .collect();
-            eval_path(&paths, value)
+            path_segments(&paths, value)
         }
         _ => &EntityValue::Null,
     }
@@ -170,34 +1260,584 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_string_functions() {
+        let value = |v: serde_json::Value| EntityValue::from(v);
+        let source = value(json!([" a-b ", "c-d", 1, null]));
+        assert_eq!(json!(["a-b", "c-d"]), trim(&source));
+        assert_eq!(
+            json!("a-b"),
+            strip(&json!(" -").into(), &value(json!(" -a-b-")))
+        );
+        assert_eq!(json!(["a-b ", "c-d"]), lstrip(&null_literal(), &source));
+        assert_eq!(
+            json!(" a-b"),
+            rstrip(&null_literal(), &value(json!(" a-b \n")))
+        );
+        assert_eq!(
+            json!([" a+b ", "c+d"]),
+            replace(&string_literal("-"), &string_literal("+"), &source)
+        );
+        assert_eq!(json!([]), trim(&number_literal(1)));
+    }
+
+    #[test]
+    fn test_substring() {
+        let s = string_literal("æøå-abc");
+        let substring = |start: i32, end: &EntityValue| substring(&number_literal(start), end, &s);
+        assert_eq!(json!("øå"), substring(1, &number_literal(3)));
+        assert_eq!(json!("abc"), substring(-3, &null_literal()));
+        assert_eq!(json!("æøå-ab"), substring(0, &number_literal(-1)));
+        assert_eq!(json!(""), substring(5, &number_literal(2)));
+        assert_eq!(json!(""), substring(10, &number_literal(20)));
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let comma = string_literal(",");
+        assert_eq!(
+            json!(["a", "b", ""]),
+            split(&comma, &string_literal("a,b,"))
+        );
+        assert_eq!(
+            json!(["a", "b", "c"]),
+            split(&comma, &json!(["a,b", 1, "c"]).into())
+        );
+        assert_eq!(
+            json!(["a", "b"]),
+            split(&string_literal(""), &string_literal("ab"))
+        );
+        assert_eq!(json!([]), split(&null_literal(), &string_literal("a")));
+        assert_eq!(json!("a,b"), join(&comma, &json!(["a", 1, "b"]).into()));
+        assert_eq!(
+            json!("ab"),
+            join(&null_literal(), &json!(["a", "b"]).into())
+        );
+        assert_eq!(json!(null), join(&comma, &number_literal(1)));
+    }
+
+    #[test]
+    fn test_length_and_affixes() {
+        assert_eq!(json!(3), length(&string_literal("æøå")));
+        assert_eq!(json!(2), length(&json!([1, null]).into()));
+        assert_eq!(json!(1), length(&json!({"a": 1}).into()));
+        assert_eq!(json!(null), length(&number_literal(1)));
+        let source = json!(["foo", "bar", 1]).into();
+        let prefix = string_literal("f");
+        assert_eq!(json!([true, false]), startswith(&prefix, &source));
+        assert_eq!(
+            json!([false, true]),
+            endswith(&string_literal("r"), &source)
+        );
+        assert_eq!(
+            json!(false),
+            startswith(&null_literal(), &string_literal("a"))
+        );
+    }
+
+    #[test]
+    fn test_justify() {
+        let width = number_literal(4);
+        assert_eq!(
+            json!("ab  "),
+            ljust(&width, &null_literal(), &string_literal("ab"))
+        );
+        assert_eq!(
+            json!(["00ab", "abcde"]),
+            rjust(&width, &string_literal("0"), &json!(["ab", "abcde"]).into())
+        );
+        assert_eq!(json!("ab"), eval(r#" ["ljust", 1000000000000, "ab"] "#));
+        assert_eq!(
+            json!(["ab"]),
+            eval(r#" ["rjust", 1048577, "0", ["list", "ab"]] "#)
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        let source: EntityValue = json!([
+            "a", 1, 1.5, true, null, "~rhttp://db.no", "~:foo:bar", "~f1.50", "~uabc", [1], {"b": 2}
+        ])
+        .into();
+        assert_eq!(
+            json!([
+                "a",
+                "1",
+                "1.5",
+                "true",
+                "http://db.no",
+                "~:foo:bar",
+                "1.50",
+                "abc",
+                "[1]",
+                "{\"b\":2}"
+            ]),
+            string(None, &source)
+        );
+        assert_eq!(json!(null), string(None, &null_literal()));
+        assert_eq!(
+            json!("~t2014-07-08"),
+            string(None, &string_literal("~t2014-07-08"))
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let source = json!(["foo.txt", "bar.csv", 1]).into();
+        assert_eq!(
+            json!([true, false]),
+            matches(&string_literal("*.txt"), &source)
+        );
+        assert_eq!(
+            json!(true),
+            matches(&string_literal("b?r*"), &string_literal("bar.csv"))
+        );
+        assert_eq!(json!([false, false]), matches(&null_literal(), &source));
+    }
+
+    #[test]
+    fn test_regex() {
+        let source = json!(["a1b22", "c", "d333", null]).into();
+        let all = EntityValue::Bool(true);
+        let digits = Regex::new(r"\d+").unwrap();
+        assert_eq!(
+            json!(["1", "333"]),
+            regex(&digits, &null_literal(), &source)
+        );
+        assert_eq!(json!(["1", "22", "333"]), regex(&digits, &all, &source));
+        assert_eq!(
+            json!(null),
+            regex(&digits, &null_literal(), &string_literal("c"))
+        );
+        assert_eq!(json!([]), regex(&digits, &all, &string_literal("c")));
+
+        let group = Regex::new(r"([a-z])\d").unwrap();
+        assert_eq!(
+            json!("a"),
+            regex(&group, &null_literal(), &string_literal("a1b2"))
+        );
+        assert_eq!(
+            json!(["a", "b"]),
+            regex(&group, &all, &string_literal("a1b2"))
+        );
+        let groups = Regex::new(r"([a-z])(\d)?(x)?").unwrap();
+        assert_eq!(
+            json!([["a", "1", null], ["b", null, null]]),
+            regex(&groups, &all, &string_literal("a1b"))
+        );
+    }
+
+    #[test]
+    fn test_regex_sub() {
+        let pattern = Regex::new(r"(\w+)@(\w+)").unwrap();
+        assert_eq!(
+            json!(["b at a, d at c", "none"]),
+            regex_sub(
+                &pattern,
+                &string_literal("$2 at $1"),
+                &json!(["a@b, c@d", "none", 1]).into()
+            )
+        );
+        assert_eq!(
+            json!("a@b"),
+            regex_sub(&pattern, &null_literal(), &string_literal("a@b"))
+        );
+    }
+
+    #[test]
+    fn test_create() {
+        let mut target = Target::new();
+        target.add("_id", string_literal("1"));
+        target.create(json!([{"_id": "2"}, {"_id": "3", "_parent": "0"}]).into());
+        target.create(set_ids(json!({"a": "4"}).into(), |entity| {
+            path_segments(&["a"], entity).clone()
+        }));
+        let expected: Vec<EntityValue> = vec![
+            json!({"_id": "2", "_parent": "1"}).into(),
+            json!({"_id": "3", "_parent": "0"}).into(),
+            json!({"_id": "4", "_parent": "1", "a": "4"}).into(),
+            json!({"_id": "1"}).into(),
+        ];
+        assert_eq!(expected, target.finish().unwrap());
+
+        for (value, reason) in [
+            (json!("bar"), "created entities must be objects"),
+            (json!(null), "created entities must be objects"),
+            (json!({"a": 1}), "created entities must have an '_id'"),
+            (json!({"_id": 1}), "'_id' must be a string"),
+        ] {
+            let mut target = Target::new();
+            target.create(json!([{"_id": "1"}, value.clone()]).into());
+            assert_eq!(2, target.output().len());
+            assert_eq!(
+                Error::InvalidEntity(value.into(), reason),
+                target.finish().unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_into_entities() {
+        let source = Entity {
+            deleted: true,
+            timestamp: 1,
+            ..Entity::new("1")
+        };
+        let mut target = Target::new();
+        target.add("_ts", json!(2).into());
+        target.add("_extra", string_literal("x"));
+        target.create(json!({"_id": "2", "_hash": "h"}).into());
+        target.filter();
+        // only `_` properties are left, so both have the hash of an empty object
+        let empty: EntityValue = json!({}).into();
+        let child = Entity {
+            deleted: true,
+            hash: empty.content_hash(),
+            content: BTreeMap::from([("_parent".to_owned(), string_literal("1"))]),
+            ..Entity::new("2")
+        };
+        let entity = Entity {
+            deleted: true,
+            filtered: true,
+            hash: empty.content_hash(),
+            content: BTreeMap::from([("_extra".to_owned(), string_literal("x"))]),
+            ..Entity::new("1")
+        };
+        assert_eq!(vec![child, entity], target.into_entities(&source).unwrap());
+
+        let mut target = Target::new();
+        target.add("_id", json!(1).into());
+        assert_eq!(
+            Error::InvalidEntity(json!({"_id": 1}).into(), "'_id' must be a string"),
+            target.into_entities(&source).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_filter_and_discard() {
+        let mut target = Target::new();
+        target.add("a", string_literal("a"));
+        target.create(json!({"_id": "b", "b": "b"}).into());
+        target.filter();
+        let expected: Vec<EntityValue> = vec![
+            json!({"_id": "b", "b": "b"}).into(),
+            json!({"a": "a", "_filtered": true}).into(),
+        ];
+        assert_eq!(expected, target.output());
+        target.discard();
+        assert_eq!(expected[..1], target.output());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut target = Target::new();
+        target.add("a", string_literal("a"));
+        target.add("nested", json!({"x": 1}).into());
+        target.merge(
+            json!([
+                {"_id": "1", "b": "b", "nested": {"y": 2}},
+                null,
+                "ignored",
+                [{"a": null}]
+            ])
+            .into(),
+        );
+        let expected: EntityValue = json!({"a": null, "b": "b", "nested": {"y": 2}}).into();
+        assert_eq!(&expected, target.entity());
+    }
+
+    #[test]
+    fn test_merge_union() {
+        let mut target = Target::new();
+        target.add("a", string_literal("a"));
+        target.add("b", json!(["x", "y"]).into());
+        target.add("c", null_literal());
+        target.merge_union(
+            json!([
+                {"a": "b", "b": ["y", "z"], "c": "c", "d": null},
+                {"a": "a", "nested": {"x": 1}},
+                {"nested": {"y": 2}}
+            ])
+            .into(),
+        );
+        let expected: EntityValue = json!({
+            "a": ["a", "b"],
+            "b": ["x", "y", "z"],
+            "c": "c",
+            "d": null,
+            "nested": [{"x": 1}, {"y": 2}]
+        })
+        .into();
+        assert_eq!(&expected, target.entity());
+    }
+
+    #[test]
+    fn test_default_and_rename() {
+        let mut target = Target::new();
+        target.add("a", string_literal("a"));
+        target.add("b", null_literal());
+        target.set_default("a", string_literal("x"));
+        target.set_default("b", string_literal("y"));
+        target.set_default("c", string_literal("z"));
+        target.rename("a", "b");
+        target.rename("d", "e");
+        let expected: EntityValue = json!({"b": "a", "c": "z"}).into();
+        assert_eq!(&expected, target.entity());
+    }
+
+    #[test]
+    fn test_property_order() {
+        let mut target = Target::new();
+        target.add("z", string_literal("z"));
+        target.add("_id", string_literal("1"));
+        target.add("a", json!({"y": 1, "x": 2}).into());
+        assert_eq!(
+            r#"{"_id":"1","a":{"x":2,"y":1},"z":"z"}"#,
+            serde_json::to_string(target.entity()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "foo"));
+        assert!(glob_match("tmp_*", "tmp_foo"));
+        assert!(glob_match("*_id", "foo_id"));
+        assert!(glob_match("f?o*", "fooo"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("tmp_*", "foo"));
+        assert!(!glob_match("f?o", "fo"));
+        assert!(!glob_match("a*b", "aXbY"));
+    }
+
+    #[test]
+    fn test_copy_and_remove() {
+        let source = json!({
+            "_id": "1",
+            "_deleted": false,
+            "_updated": 2,
+            "name": "a",
+            "tmp_x": 1,
+            "tmp_y": 2
+        })
+        .into();
+        let mut target = Target::new();
+        target.copy(&["*"], &["_*"], &source);
+        target.remove(&["tmp_*"], &["tmp_y"]);
+        let expected: EntityValue = json!({"_id": "1", "name": "a", "tmp_y": 2}).into();
+        assert_eq!(&expected, target.entity());
+
+        let mut target = Target::new();
+        target.copy(&["_deleted", "n*"], &[] as &[&str], &source);
+        target.remove(&["*", "_*"], &[] as &[&str]);
+        let expected: EntityValue = json!({"_id": "1"}).into();
+        assert_eq!(&expected, target.entity());
+    }
+
     #[test]
     fn test_concat() {
         assert_eq!(json!("aB"), concat(&json!(["a", "B", 1, null, []]).into()));
         assert_eq!(json!("a"), concat(&json!("a").into()));
     }
 
-    // #[test]
-    // fn test_boolean() {
-    //     //TODO impl eval
-    //     assert_eq!(json(r#"false"#), eval(r#" ["boolean", "false"] "#));
-    //     assert_eq!(json(r#"null"#), eval(r#" ["boolean", null] "#));
-    //     assert_eq!(json(r#" [true, true, false] "#), eval(r#"
-    //         ["boolean",
-    //             ["list", "true", "~rhttp://www.example.org/",
-    //             "True", false, 1234]]
-    //     "#));
-    //     assert_eq!(json(r#" [true, false, false, false] "#), eval(r#"
-    //         ["boolean", ["boolean", false],
-    //             ["list", "true", "~rhttp://www.example.org/",
-    //             "124.4", "FALSE"]]
-    //     "#));
-    //     assert_eq!(json(r#" [true, “n/a”, “n/a”]"#), eval(r#"
-    //         ["boolean", ["string", "n/a"],
-    //             ["list", "true", "~rhttp://www.example.org/", "124.4"]]
-    //     "#));
-    //     assert_eq!(json(r#" [true, “http://www.example.org/”, false] "#), eval(r#"
-    //         ["boolean", ["string", "_."],
-    //             ["list", "true", "~rhttp://www.example.org/", "False"]]
-    //     "#));
-    // }
+    #[test]
+    fn test_arithmetic() {
+        let n = |v: serde_json::Value| EntityValue::from(v);
+        assert_eq!(json!(3), plus(&n(json!(1)), &n(json!(2))));
+        assert_eq!(json!(3.5), plus(&n(json!(1)), &n(json!(2.5))));
+        assert_eq!(n(json!("~f3.5")), plus(&n(json!(1)), &n(json!("~f2.5"))));
+        assert_eq!(n(json!("~f0.3")), plus(&n(json!(0.1)), &n(json!("~f0.2"))));
+        assert_eq!(json!(-1), minus(&n(json!(1)), &n(json!(2))));
+        assert_eq!(json!(6.0), multiply(&n(json!(2)), &n(json!(3.0))));
+        assert_eq!(json!(2), divide(&n(json!(6)), &n(json!(3))));
+        assert_eq!(json!(2.5), divide(&n(json!(5)), &n(json!(2))));
+        assert_eq!(json!(null), divide(&n(json!(5)), &n(json!(0))));
+        assert_eq!(json!(null), divide(&n(json!("~f5")), &n(json!(0.0))));
+        assert_eq!(json!(null), plus(&n(json!(1)), &string_literal("1")));
+        assert_eq!(json!(2), modulo(&n(json!(-7)), &n(json!(3))));
+        assert_eq!(json!(-2), modulo(&n(json!(7)), &n(json!(-3))));
+        assert_eq!(json!(1.5), modulo(&n(json!(-1.5)), &n(json!(3))));
+        assert_eq!(n(json!("~f1.5")), modulo(&n(json!("~f-1.5")), &n(json!(3))));
+        assert_eq!(json!(null), modulo(&n(json!(1)), &n(json!(0))));
+        assert_eq!(json!(1024), pow(&n(json!(2)), &n(json!(10))));
+        assert_eq!(json!(0.25), pow(&n(json!(2)), &n(json!(-2))));
+        assert_eq!(n(json!("~f0.25")), pow(&n(json!("~f0.5")), &n(json!(2))));
+        assert_eq!(n(json!("~f4")), pow(&n(json!("~f0.5")), &n(json!(-2))));
+        assert_eq!(json!(null), pow(&n(json!(10.0)), &n(json!(400))));
+
+        // overflow continues in decimals
+        let max = n(json!(i64::MAX));
+        assert_eq!(n(json!("~f9223372036854775808")), plus(&max, &n(json!(1))));
+        assert_eq!(
+            n(json!("~f85070591730234615847396907784232501249")),
+            multiply(&max, &max)
+        );
+        assert_eq!(
+            n(json!("~f18446744073709551616")),
+            pow(&n(json!(2)), &n(json!(64)))
+        );
+        assert_eq!(n(json!("~f9223372036854775808")), abs(&n(json!(i64::MIN))));
+        // beyond the largest exponent only powers that don't grow are computed
+        assert_eq!(json!(1), eval(r#" ["pow", 1, 5000] "#));
+        assert_eq!(json!(0), eval(r#" ["pow", 0, 2000] "#));
+        assert_eq!(json!("~f1"), pow(&n(json!("~f1")), &n(json!(5000))));
+        assert_eq!(json!("~f1"), pow(&n(json!(1)), &n(json!(10_000_000_000u64))));
+        assert_eq!(json!("~f-1"), pow(&n(json!("~f-1")), &n(json!(5001))));
+        assert_eq!(json!("~f1"), pow(&n(json!(-1)), &n(json!(10_000_000_000u64))));
+        assert_eq!(json!("~f0"), pow(&n(json!("~f0")), &n(json!(2000))));
+        assert_eq!(json!(null), pow(&n(json!("~f0")), &n(json!(-2000))));
+        assert_eq!(json!(null), pow(&n(json!("~f2")), &n(json!(2000))));
+        assert_eq!(
+            n(json!("~f9223372036854775808")),
+            eval(r#" ["divide", -9223372036854775808, -1] "#)
+        );
+        assert_eq!(
+            n(json!("~f18446744073709551616")),
+            plus(&n(json!(u64::MAX)), &n(json!(1)))
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_lists() {
+        let n = |v: serde_json::Value| EntityValue::from(v);
+        assert_eq!(json!([2, 3]), plus(&n(json!([1, "a", 2])), &n(json!(1))));
+        assert_eq!(json!([9, 8]), minus(&n(json!(10)), &n(json!([1, 2]))));
+        assert_eq!(
+            json!([3, 8]),
+            multiply(&n(json!([1, 2, 3])), &n(json!([3, 4])))
+        );
+        assert_eq!(json!([1, 2.5]), abs(&n(json!([-1, null, -2.5]))));
+        assert_eq!(json!(null), abs(&string_literal("-1")));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let n = |v: serde_json::Value| EntityValue::from(v);
+        let values = n(json!([1, 1.5, -1.5, 2.675, "~f-2.5"]));
+        assert_eq!(
+            json!([1, 2, -2, 3, "~f-3"]),
+            round(&null_literal(), &values)
+        );
+        assert_eq!(
+            json!([1, 1.5, -1.5, 2.68, "~f-2.50"]),
+            round(&number_literal(2), &values)
+        );
+        assert_eq!(
+            json!(1200),
+            round(&number_literal(-2), &number_literal(1150))
+        );
+        assert_eq!(json!([1, 1, -2, 2, "~f-3"]), floor(&values));
+        assert_eq!(json!([1, 2, -1, 3, "~f-2"]), ceil(&values));
+        assert_eq!(json!(1e300), floor(&n(json!(1e300))));
+        assert_eq!(json!(null), round(&string_literal("2"), &number_literal(1)));
+        assert_eq!(json!(null), eval(r#" ["round", 1000000000000, 1.5] "#));
+        assert_eq!(json!(null), eval(r#" ["round", 1000000000000, "~f1.5"] "#));
+        assert_eq!(json!(null), eval(r#" ["round", -1000000000000, 1] "#));
+        assert_eq!(json!(5), eval(r#" ["round", 2000, 5] "#));
+        assert_eq!(json!(1200), eval(r#" ["round", -2, 1234.5] "#));
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let n = |v: serde_json::Value| EntityValue::from(v);
+        let values = n(json!([3, "4", 1.5, "~f-2.5", null]));
+        assert_eq!(n(json!("~f2.0")), sum(&values));
+        assert_eq!(json!(4), sum(&n(json!([1, 3]))));
+        assert_eq!(json!(0), sum(&n(json!([]))));
+        assert_eq!(json!(5), sum(&number_literal(5)));
+        assert_eq!(n(json!("~f-2.5")), min(&values));
+        assert_eq!(json!(3), max(&values));
+        assert_eq!(json!(1), min(&n(json!([1, 1.0]))));
+        assert_eq!(json!(null), max(&n(json!(["a"]))));
+    }
+
+    fn json(value: &str) -> EntityValue {
+        value.parse().unwrap()
+    }
+
+    fn eval(expression: &str) -> EntityValue {
+        let source = EntityValue::Null;
+        crate::eval::eval_str(expression, &crate::eval::Context::new(&source)).unwrap()
+    }
+
+    #[test]
+    fn test_boolean() {
+        assert_eq!(json(r#"false"#), eval(r#" ["boolean", "false"] "#));
+        assert_eq!(json(r#"null"#), eval(r#" ["boolean", null] "#));
+        assert_eq!(json(r#" [true, true, false] "#), eval(r#"
+            ["boolean",
+                ["list", "true", "~rhttp://www.example.org/",
+                "True", false, 1234]]
+        "#));
+        assert_eq!(json(r#" [true, false, false, false] "#), eval(r#"
+            ["boolean", ["boolean", false],
+                ["list", "true", "~rhttp://www.example.org/",
+                "124.4", "FALSE"]]
+        "#));
+        assert_eq!(json(r#" [true, "n/a", "n/a"]"#), eval(r#"
+            ["boolean", ["string", "n/a"],
+                ["list", "true", "~rhttp://www.example.org/", "124.4"]]
+        "#));
+        assert_eq!(json(r#" [true, "http://www.example.org/", false] "#), eval(r#"
+            ["boolean", ["string", "_."],
+                ["list", "true", "~rhttp://www.example.org/", "False"]]
+        "#));
+    }
+
+    #[test]
+    fn test_integer_float_decimal() {
+        let source = json(r#" [1, -1.9, "~f2.5", "3", "4.5", "~:a:b", "~bAQ==", true, null] "#);
+        assert_eq!(json(r#" [1, -1, 2, 3] "#), integer(None, &source));
+        assert_eq!(
+            json(r#" [1.0, -1.9, 2.5, 3.0, 4.5] "#),
+            float(None, &source)
+        );
+        assert_eq!(
+            json(r#" ["~f1", "~f-1.9", "~f2.5", "~f3", "~f4.5"] "#),
+            decimal(None, &source)
+        );
+        assert_eq!(json(r#"null"#), integer(None, &string_literal("x")));
+        assert_eq!(json(r#"null"#), float(None, &string_literal("inf")));
+        let zero = |_: &EntityValue| number_literal(0);
+        assert_eq!(
+            json(r#" [1, -1, 2, 3, 0, 0, 0, 0, 0] "#),
+            integer(Some(&zero), &source)
+        );
+        assert_eq!(
+            json!(["1", "-1.9", "2.5", "3", "4.5", "~:a:b", "AQ==", "true"]),
+            string(None, &source)
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(json(r#"true"#), eval(r#" ["eq", 1, "~f1.0"] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["eq", 1, 1.0] "#));
+        assert_eq!(json(r#"false"#), eval(r#" ["eq", 1, "1"] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["neq", ["list", 1, 2], ["list", 2, 1]] "#));
+        assert_eq!(json(r#"true"#), eval(r#"
+            ["lt", "~t2024-01-01", "~t2024-01-01T00:00:01.000000000+00:00"]
+        "#));
+        assert_eq!(json(r#"true"#), eval(r#"
+            ["gte", "~t2024-01-01T00:00:00.000000000+00:00", "~t2024-01-01"]
+        "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["gt", "~:a:b", "~:a:a"] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["lte", null, false] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["in", "~f2", ["list", 1, 2]] "#));
+        assert_eq!(json(r#"false"#), eval(r#" ["in", "a", "ab"] "#));
+    }
+
+    #[test]
+    fn test_logical() {
+        assert_eq!(json(r#"true"#), eval(r#" ["and", true, ["list", false, true]] "#));
+        assert_eq!(json(r#"false"#), eval(r#" ["and", true, 1] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["or", null, "a", ["list", 1, true]] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["not", ["eq", 1, 2]] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["not", "true"] "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["is-null", "_S.a"] "#));
+        assert_eq!(json(r#" [true, true, true, false, false] "#), eval(r#"
+            ["list", ["is-empty", null], ["is-empty", ""], ["is-empty", ["list"]],
+                ["is-empty", ["list", ""]], ["is-empty", 0]]
+        "#));
+        assert_eq!(json(r#"true"#), eval(r#" ["is-not-empty", "a"] "#));
+    }
 }
@@ -3,14 +3,19 @@ use std::fmt::Display;
 use base64::{engine::general_purpose, Engine as _};
 use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct ByteWrapper(Vec<u8>);
 impl ByteWrapper {
-    pub(crate) fn deserialize(value: &str) -> ByteWrapper {
-        ByteWrapper(general_purpose::STANDARD.decode(&value[2..]).unwrap())
+    pub(crate) fn deserialize(value: &str) -> Result<ByteWrapper, Error> {
+        general_purpose::STANDARD
+            .decode(&value[2..])
+            .map(ByteWrapper)
+            .map_err(|e| Error::InvalidBytes(value.to_owned(), e))
     }
     
-    pub(crate) fn from_vec(vec: Vec<u8>) -> ByteWrapper {
+    pub fn from_vec(vec: Vec<u8>) -> ByteWrapper {
         ByteWrapper(vec)
     }
     
@@ -18,7 +23,7 @@ impl ByteWrapper {
         value.starts_with("~b")
     }
     
-    pub(crate) fn from_array(arg: &[u8; 5]) -> ByteWrapper {
+    pub fn from_array(arg: &[u8; 5]) -> ByteWrapper {
         ByteWrapper(arg.to_vec())
     }
 }
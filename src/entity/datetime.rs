@@ -1,22 +1,33 @@
 use core::fmt;
 use std::fmt::Display;
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct Date(NaiveDate);
 impl Date {
-    pub(crate) fn deserialize(value: &str) -> Date {
-        Self::parse(&value[2..])
+    pub(crate) fn deserialize(value: &str) -> Result<Date, Error> {
+        NaiveDate::parse_from_str(&value[2..], DATE_FMT)
+            .map(Date)
+            .map_err(|e| Error::InvalidDate(value.to_owned(), e))
     }
     
     pub(crate) fn can_deserialize(value: &str) -> bool {
         value.starts_with("~t") && !value.contains("T")
     }
     
-    pub(crate) fn parse(arg: &str) -> Date {
-        Date(NaiveDate::parse_from_str(&arg, DATE_FMT).unwrap())
+    pub fn parse(arg: &str) -> Result<Date, Error> {
+        NaiveDate::parse_from_str(arg, DATE_FMT)
+            .map(Date)
+            .map_err(|e| Error::InvalidDate(arg.to_owned(), e))
+    }
+
+    /// The start of the day in UTC.
+    pub fn start_of_day(&self) -> DateTime<Utc> {
+        self.0.and_time(NaiveTime::MIN).and_utc()
     }
 }
 
@@ -38,19 +49,27 @@ impl Serialize for Date {
 }
 
 // TODO consider using a long to store the nanos since epoch
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct DateTimeWrapper(DateTime<Utc>);
 impl DateTimeWrapper {
-    pub(crate) fn deserialize(value: &str) -> DateTimeWrapper {
-        Self::parse(&value[2..])
+    pub(crate) fn deserialize(value: &str) -> Result<DateTimeWrapper, Error> {
+        DateTime::parse_from_str(&value[2..], DATE_TIME_FMT)
+            .map(|d| DateTimeWrapper(d.to_utc()))
+            .map_err(|e| Error::InvalidDateTime(value.to_owned(), e))
     }
     
     pub(crate) fn can_deserialize(value: &str) -> bool {
         value.starts_with("~t") && value.contains("T")
     }
     
-    pub(crate) fn parse(arg: &str) -> DateTimeWrapper {
-        DateTimeWrapper(DateTime::parse_from_str(&arg, DATE_TIME_FMT).unwrap().to_utc())
+    pub fn parse(arg: &str) -> Result<DateTimeWrapper, Error> {
+        DateTime::parse_from_str(arg, DATE_TIME_FMT)
+            .map(|d| DateTimeWrapper(d.to_utc()))
+            .map_err(|e| Error::InvalidDateTime(arg.to_owned(), e))
+    }
+
+    pub fn as_date_time(&self) -> &DateTime<Utc> {
+        &self.0
     }
 }
 
@@ -1,22 +1,47 @@
 use core::fmt;
 use std::{fmt::Display, str::FromStr};
 
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, ToPrimitive};
 use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct BigDecimalWrapper(BigDecimal);
 impl BigDecimalWrapper {
-    pub(crate) fn deserialize(value: &str) -> BigDecimalWrapper {
-        BigDecimalWrapper(BigDecimal::from_str(&value[2..]).unwrap())
+    pub(crate) fn deserialize(value: &str) -> Result<BigDecimalWrapper, Error> {
+        BigDecimal::from_str(&value[2..])
+            .map(BigDecimalWrapper)
+            .map_err(|e| Error::InvalidDecimal(value.to_owned(), e))
     }
     
     pub(crate) fn can_deserialize(value: &str) -> bool {
         value.starts_with("~f")
     }
     
-    pub(crate) fn parse(arg: &str) -> BigDecimalWrapper {
-        BigDecimalWrapper(BigDecimal::from_str(arg).unwrap())
+    pub fn parse(arg: &str) -> Result<BigDecimalWrapper, Error> {
+        BigDecimal::from_str(arg)
+            .map(BigDecimalWrapper)
+            .map_err(|e| Error::InvalidDecimal(arg.to_owned(), e))
+    }
+
+    /// The integer part, if it fits in an `i64`.
+    pub fn to_i64(&self) -> Option<i64> {
+        self.0.with_scale(0).to_i64()
+    }
+
+    pub fn to_f64(&self) -> Option<f64> {
+        self.0.to_f64().filter(|f| f.is_finite())
+    }
+
+    pub fn as_big_decimal(&self) -> &BigDecimal {
+        &self.0
+    }
+}
+
+impl From<BigDecimal> for BigDecimalWrapper {
+    fn from(value: BigDecimal) -> Self {
+        BigDecimalWrapper(value)
     }
 }
 
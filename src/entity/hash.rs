@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::entity::{Entity, EntityValue};
+
+impl EntityValue {
+    /// A SHA-256 hex digest of the value that only depends on its content. If the value is an
+    /// object, its `_` properties are left out, nested ones are part of the content.
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        match self {
+            EntityValue::Object(map) => write_object(&mut hasher, content(map)),
+            value => write_value(&mut hasher, value),
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+impl Entity {
+    /// The hash of the content of the entity, without any `_` properties. Whether the
+    /// entity is deleted isn't part of it, so `_deleted` has to be compared as well.
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        write_object(&mut hasher, content(&self.content));
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Sets `_hash` to the `content_hash` of the entity.
+    pub fn update_hash(&mut self) {
+        self.hash = self.content_hash();
+    }
+}
+
+fn content(map: &BTreeMap<String, EntityValue>) -> Vec<(&String, &EntityValue)> {
+    map.iter()
+        .filter(|(name, _)| !name.starts_with('_'))
+        .collect()
+}
+
+/// Every value starts with a tag for its type, and strings and collections with their
+/// length, so that different values can't be written as the same bytes.
+fn write_value(hasher: &mut Sha256, value: &EntityValue) {
+    match value {
+        EntityValue::Null => hasher.update(b"n"),
+        EntityValue::Bool(b) => hasher.update(if *b { b"t" } else { b"f" }),
+        EntityValue::Number(n) => write_str(hasher, b'#', &n.to_string()),
+        EntityValue::String(s) => write_str(hasher, b's', s),
+        // normalized, so that decimals of different scales but the same value hash the same
+        EntityValue::Decimal(d) => {
+            write_str(hasher, b'd', &d.as_big_decimal().normalized().to_string())
+        }
+        EntityValue::Array(items) => {
+            write_len(hasher, b'[', items.len());
+            items.iter().for_each(|item| write_value(hasher, item));
+        }
+        EntityValue::Object(map) => write_object(hasher, map.iter().collect()),
+        // transit values are hashed as their transit string, but with their own tag
+        transit => write_str(
+            hasher,
+            b'~',
+            &serde_json::to_string(transit).unwrap_or_default(),
+        ),
+    }
+}
+
+/// The properties are sorted by name, as `EntityValue::Object` keeps them.
+fn write_object(hasher: &mut Sha256, properties: Vec<(&String, &EntityValue)>) {
+    write_len(hasher, b'{', properties.len());
+    for (name, value) in properties {
+        write_str(hasher, b'k', name);
+        write_value(hasher, value);
+    }
+}
+
+fn write_str(hasher: &mut Sha256, tag: u8, s: &str) {
+    write_len(hasher, tag, s.len());
+    hasher.update(s.as_bytes());
+}
+
+fn write_len(hasher: &mut Sha256, tag: u8, len: usize) {
+    hasher.update([tag]);
+    hasher.update((len as u64).to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{BigDecimalWrapper, URI};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn hash(value: serde_json::Value) -> String {
+        EntityValue::from(value).content_hash()
+    }
+
+    #[test]
+    fn content_hash() {
+        let a = hash(json!({"a": 1, "b": ["x", {"c": null}]}));
+        assert_eq!(64, a.len());
+        // stored hashes must stay valid between versions
+        assert_eq!(
+            "582984ea33b61c673ed9be78050334b5f2e0c1b1278a0afb10bb7dd275ac85a4",
+            hash(json!({"a": 1}))
+        );
+        assert_eq!(a, hash(json!({"b": ["x", {"c": null}], "a": 1})));
+        assert_eq!(
+            a,
+            hash(json!({"a": 1, "b": ["x", {"c": null}], "_id": "1", "_ts": 2}))
+        );
+        assert_ne!(a, hash(json!({"a": 1, "b": ["x", {"c": null, "_d": 1}]})));
+        assert_ne!(a, hash(json!({"a": "1", "b": ["x", {"c": null}]})));
+        assert_ne!(a, hash(json!({"a": 1, "b": [["x"], {"c": null}]})));
+        assert_ne!(hash(json!(["ab", "c"])), hash(json!(["a", "bc"])));
+
+        let uri = EntityValue::URI(URI::parse("http://db.no"));
+        assert_eq!(uri.content_hash(), hash(json!("~rhttp://db.no")));
+        assert_ne!(
+            uri.content_hash(),
+            EntityValue::String("~rhttp://db.no".into()).content_hash()
+        );
+        let decimal = EntityValue::Decimal(BigDecimalWrapper::parse("1.5").unwrap());
+        assert_ne!(decimal.content_hash(), hash(json!(1.5)));
+        assert_eq!(decimal.content_hash(), hash(json!("~f1.50")));
+        assert_ne!(decimal.content_hash(), hash(json!("~f15")));
+    }
+
+    #[test]
+    fn entity_hash() {
+        let mut entity: Entity = serde_json::from_value(json!({"_id": "1", "a": 1})).unwrap();
+        entity.update_hash();
+        assert_eq!(
+            entity.hash,
+            EntityValue::from(json!({"_id": "2", "a": 1})).content_hash()
+        );
+        let deleted = Entity {
+            deleted: true,
+            ..entity.clone()
+        };
+        assert_eq!(entity.hash, deleted.content_hash());
+    }
+}
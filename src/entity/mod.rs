@@ -1,13 +1,15 @@
 use core::fmt::{self, Debug};
-use std::collections::HashMap;
+use std::{collections::BTreeMap, str::FromStr};
 
 use serde::{
-    de::{MapAccess, SeqAccess, Visitor},
+    de::{DeserializeSeed, MapAccess, SeqAccess, Visitor},
     Deserialize, Serialize,
 };
 use serde_json::{Number, Value};
 
-use crate::entity::{
+use crate::error::Error;
+
+pub use crate::entity::{
     bytes::ByteWrapper,
     datetime::{Date, DateTimeWrapper},
     decimal::BigDecimalWrapper,
@@ -19,32 +21,141 @@ use crate::entity::{
 mod bytes;
 mod datetime;
 mod decimal;
+mod hash;
 mod ni;
+mod order;
 mod uri;
 mod uuid;
 
-//TODO not sure if we need this wrapper in this library
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-struct Entity {
+/// An entity with its system properties, the envelope DTL is executed against.
+/// It is serialized as its `EntityValue`, so system properties are sorted with the rest.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(into = "EntityValue")]
+pub struct Entity {
     #[serde(rename = "_id")]
-    id: String,
-    #[serde(rename = "_deleted")]
-    deleted: bool,
-    #[serde(rename = "_ts")]
-    timestamp: u128,
-    #[serde(rename = "_filtered")]
-    filtered: bool,
-    #[serde(rename = "_updated")]
-    updated: u64,
-    #[serde(rename = "_hash")]
-    hash: String,
-    #[serde(rename = "_previous")]
-    previous: Option<u64>,
+    pub id: String,
+    #[serde(rename = "_deleted", default)]
+    pub deleted: bool,
+    #[serde(rename = "_ts", default)]
+    pub timestamp: u128,
+    #[serde(rename = "_filtered", default)]
+    pub filtered: bool,
+    #[serde(rename = "_updated", default)]
+    pub updated: u64,
+    #[serde(rename = "_hash", default)]
+    pub hash: String,
+    #[serde(rename = "_previous", default)]
+    pub previous: Option<u64>,
     #[serde(flatten)]
-    content: HashMap<String, EntityValue>,
+    pub content: BTreeMap<String, EntityValue>,
+}
+
+/// The system properties of an entity, apart from `_id`.
+pub const SYSTEM_PROPERTIES: [&str; 6] = [
+    "_deleted",
+    "_ts",
+    "_filtered",
+    "_updated",
+    "_hash",
+    "_previous",
+];
+
+/// The system properties that are managed by whoever stores entities, not by DTL.
+pub const MANAGED_PROPERTIES: [&str; 4] = ["_ts", "_updated", "_hash", "_previous"];
+
+impl Entity {
+    pub fn new(id: impl Into<String>) -> Self {
+        Entity {
+            id: id.into(),
+            deleted: false,
+            timestamp: 0,
+            filtered: false,
+            updated: 0,
+            hash: String::new(),
+            previous: None,
+            content: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<Entity> for EntityValue {
+    fn from(entity: Entity) -> Self {
+        let number = |n: Option<Number>| n.map_or(EntityValue::Null, EntityValue::Number);
+        let mut map = entity.content;
+        map.insert("_id".to_owned(), EntityValue::String(entity.id));
+        map.insert("_deleted".to_owned(), EntityValue::Bool(entity.deleted));
+        let timestamp = number(Number::from_u128(entity.timestamp));
+        map.insert("_ts".to_owned(), timestamp);
+        map.insert("_filtered".to_owned(), EntityValue::Bool(entity.filtered));
+        map.insert("_updated".to_owned(), number(Some(entity.updated.into())));
+        map.insert("_hash".to_owned(), EntityValue::String(entity.hash));
+        let previous = number(entity.previous.map(Number::from));
+        map.insert("_previous".to_owned(), previous);
+        EntityValue::Object(map)
+    }
 }
 
-#[derive(PartialEq, Clone)]
+impl TryFrom<EntityValue> for Entity {
+    type Error = Error;
+
+    /// Reads the system properties of an object, missing or null ones get their defaults.
+    fn try_from(value: EntityValue) -> Result<Self, Self::Error> {
+        let EntityValue::Object(mut content) = value else {
+            return Err(Error::InvalidEntity(value, "entities must be objects"));
+        };
+        let (id, reason) = match content.get("_id") {
+            Some(EntityValue::String(id)) => (id.clone(), invalid_system_property(&content)),
+            Some(_) => (String::new(), Some("'_id' must be a string")),
+            None => (String::new(), Some("entities must have an '_id'")),
+        };
+        if let Some(reason) = reason {
+            return Err(Error::InvalidEntity(EntityValue::Object(content), reason));
+        }
+        let flag = |name: &str| matches!(content.get(name), Some(EntityValue::Bool(true)));
+        let integer = |name: &str| match content.get(name) {
+            Some(EntityValue::Number(n)) => n.as_u64(),
+            _ => None,
+        };
+        let mut entity = Entity {
+            deleted: flag("_deleted"),
+            timestamp: integer("_ts").unwrap_or_default().into(),
+            filtered: flag("_filtered"),
+            updated: integer("_updated").unwrap_or_default(),
+            hash: match content.get("_hash") {
+                Some(EntityValue::String(hash)) => hash.clone(),
+                _ => String::new(),
+            },
+            previous: integer("_previous"),
+            ..Entity::new(id)
+        };
+        content.retain(|name, _| name != "_id" && !SYSTEM_PROPERTIES.contains(&name.as_str()));
+        entity.content = content;
+        Ok(entity)
+    }
+}
+
+/// Why a system property has the wrong type, null counts as missing.
+fn invalid_system_property(content: &BTreeMap<String, EntityValue>) -> Option<&'static str> {
+    SYSTEM_PROPERTIES.iter().find_map(|&name| {
+        let value = content.get(name)?;
+        let is_bool = matches!(value, EntityValue::Bool(_));
+        let is_integer = matches!(value, EntityValue::Number(n) if n.is_u64());
+        let (valid, reason) = match name {
+            "_deleted" => (is_bool, "'_deleted' must be a boolean"),
+            "_filtered" => (is_bool, "'_filtered' must be a boolean"),
+            "_hash" => (
+                matches!(value, EntityValue::String(_)),
+                "'_hash' must be a string",
+            ),
+            "_ts" => (is_integer, "'_ts' must be an integer"),
+            "_updated" => (is_integer, "'_updated' must be an integer"),
+            _ => (is_integer, "'_previous' must be an integer"),
+        };
+        (!valid && *value != EntityValue::Null).then_some(reason)
+    })
+}
+
+#[derive(PartialEq, Eq, Clone)]
 pub enum EntityValue {
     Null,
     Bool(bool),
@@ -59,7 +170,8 @@ pub enum EntityValue {
     NI(NI),
     Decimal(BigDecimalWrapper),
     Array(Vec<EntityValue>),
-    Object(HashMap<String, EntityValue>),
+    /// Properties are kept sorted by name, so serialized objects are the same every time.
+    Object(BTreeMap<String, EntityValue>),
 }
 
 impl Debug for EntityValue {
@@ -95,9 +207,18 @@ impl From<Value> for EntityValue {
     }
 }
 
-impl Into<Value> for EntityValue {
-    fn into(self) -> Value {
-        serde_json::to_value(self).unwrap()
+// parses transit encoded json, e.g. in code generated from DTL
+impl FromStr for EntityValue {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl From<EntityValue> for Value {
+    fn from(value: EntityValue) -> Self {
+        serde_json::to_value(value).unwrap()
     }
 }
 
@@ -137,131 +258,182 @@ impl<'de> Deserialize<'de> for EntityValue {
     where
         D: serde::Deserializer<'de>,
     {
-        struct EntityValueVisitor;
-
-        impl<'de> Visitor<'de> for EntityValueVisitor {
-            type Value = EntityValue;
-
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("any valid JSON value")
-            }
-
-            #[inline]
-            fn visit_bool<E>(self, value: bool) -> Result<EntityValue, E> {
-                Ok(EntityValue::Bool(value))
-            }
-
-            #[inline]
-            fn visit_i64<E>(self, value: i64) -> Result<EntityValue, E> {
-                Ok(EntityValue::Number(value.into()))
-            }
-
-            fn visit_i128<E>(self, value: i128) -> Result<EntityValue, E>
-            where
-                E: serde::de::Error,
-            {
-                let de = serde::de::value::I128Deserializer::new(value);
-                Number::deserialize(de).map(EntityValue::Number)
-            }
-
-            #[inline]
-            fn visit_u64<E>(self, value: u64) -> Result<EntityValue, E> {
-                Ok(EntityValue::Number(value.into()))
-            }
-
-            fn visit_u128<E>(self, value: u128) -> Result<EntityValue, E>
-            where
-                E: serde::de::Error,
-            {
-                let de = serde::de::value::U128Deserializer::new(value);
-                Number::deserialize(de).map(EntityValue::Number)
-            }
-
-            #[inline]
-            fn visit_f64<E>(self, value: f64) -> Result<EntityValue, E> {
-                Ok(Number::from_f64(value).map_or(EntityValue::Null, EntityValue::Number))
-            }
-
-            #[inline]
-            fn visit_str<E>(self, value: &str) -> Result<EntityValue, E>
-            where
-                E: serde::de::Error,
-            {
-                self.visit_string(String::from(value))
-            }
-
-            #[inline]
-            fn visit_string<E>(self, value: String) -> Result<EntityValue, E> {
-                Ok(if !value.starts_with("~") {
-                    // optimization to avoid all the checks for non-transit strings
-                    EntityValue::String(value)
-                } else {
-                    if URI::can_deserialize(&value) {
-                        EntityValue::URI(URI::deserialize(&value))
-                    } else if DateTimeWrapper::can_deserialize(&value) {
-                        EntityValue::DateTime(DateTimeWrapper::deserialize(&value))
-                    } else if Date::can_deserialize(&value) {
-                        EntityValue::Date(Date::deserialize(&value))
-                    } else if ByteWrapper::can_deserialize(&value) {
-                        EntityValue::Bytes(ByteWrapper::deserialize(&value))
-                    } else if NI::can_deserialize(&value) {
-                        EntityValue::NI(NI::deserialize(&value))
-                    } else if BigDecimalWrapper::can_deserialize(&value) {
-                        EntityValue::Decimal(BigDecimalWrapper::deserialize(&value))
-                    } else if UUID::can_deserialize(&value) {
-                        EntityValue::UUID(UUID::deserialize(&value))
-                    } else {
-                        EntityValue::String(value)
-                    }
-                })
-            }
-
-            #[inline]
-            fn visit_none<E>(self) -> Result<EntityValue, E> {
-                Ok(EntityValue::Null)
-            }
-
-            #[inline]
-            fn visit_some<D>(self, deserializer: D) -> Result<EntityValue, D::Error>
-            where
-                D: serde::Deserializer<'de>,
-            {
-                Deserialize::deserialize(deserializer)
-            }
-
-            #[inline]
-            fn visit_unit<E>(self) -> Result<EntityValue, E> {
-                Ok(EntityValue::Null)
-            }
-
-            #[inline]
-            fn visit_seq<V>(self, mut visitor: V) -> Result<EntityValue, V::Error>
-            where
-                V: SeqAccess<'de>,
-            {
-                let mut vec = Vec::new();
-
-                while let Some(elem) = (visitor.next_element())? {
-                    vec.push(elem);
-                }
-
-                Ok(EntityValue::Array(vec))
-            }
-
-            fn visit_map<V>(self, mut visitor: V) -> Result<EntityValue, V::Error>
-            where
-                V: MapAccess<'de>,
-            {
-                let mut values = HashMap::new();
-
-                while let Some((key, value)) = (visitor.next_entry())? {
-                    values.insert(key, value);
-                }
-
-                Ok(EntityValue::Object(values))
-            }
+        deserializer.deserialize_any(EntityValueVisitor { lenient: false })
+    }
+}
+
+impl EntityValue {
+    /// Like `deserialize`, but keeps malformed transit strings like `"~:foo"` as
+    /// plain strings instead of failing. Can be used with `#[serde(deserialize_with)]`.
+    pub fn deserialize_lenient<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(EntityValueVisitor { lenient: true })
+    }
+
+    fn from_transit(value: String) -> Result<EntityValue, Error> {
+        Ok(if URI::can_deserialize(&value) {
+            EntityValue::URI(URI::deserialize(&value))
+        } else if DateTimeWrapper::can_deserialize(&value) {
+            EntityValue::DateTime(DateTimeWrapper::deserialize(&value)?)
+        } else if Date::can_deserialize(&value) {
+            EntityValue::Date(Date::deserialize(&value)?)
+        } else if ByteWrapper::can_deserialize(&value) {
+            EntityValue::Bytes(ByteWrapper::deserialize(&value)?)
+        } else if NI::can_deserialize(&value) {
+            EntityValue::NI(NI::deserialize(&value)?)
+        } else if BigDecimalWrapper::can_deserialize(&value) {
+            EntityValue::Decimal(BigDecimalWrapper::deserialize(&value)?)
+        } else if UUID::can_deserialize(&value) {
+            EntityValue::UUID(UUID::deserialize(&value))
+        } else {
+            EntityValue::String(value)
+        })
+    }
+}
+
+/// Deserializes an `EntityValue` as a newtype, falling back to plain strings for malformed transit values.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lenient(pub EntityValue);
+
+impl<'de> Deserialize<'de> for Lenient {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        EntityValue::deserialize_lenient(deserializer).map(Lenient)
+    }
+}
+
+// passes the leniency on to nested values
+#[derive(Clone, Copy)]
+struct EntityValueVisitor {
+    lenient: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for EntityValueVisitor {
+    type Value = EntityValue;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<EntityValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntityValueVisitor {
+    type Value = EntityValue;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("any valid JSON value")
+    }
+
+    #[inline]
+    fn visit_bool<E>(self, value: bool) -> Result<EntityValue, E> {
+        Ok(EntityValue::Bool(value))
+    }
+
+    #[inline]
+    fn visit_i64<E>(self, value: i64) -> Result<EntityValue, E> {
+        Ok(EntityValue::Number(value.into()))
+    }
+
+    fn visit_i128<E>(self, value: i128) -> Result<EntityValue, E>
+    where
+        E: serde::de::Error,
+    {
+        let de = serde::de::value::I128Deserializer::new(value);
+        Number::deserialize(de).map(EntityValue::Number)
+    }
+
+    #[inline]
+    fn visit_u64<E>(self, value: u64) -> Result<EntityValue, E> {
+        Ok(EntityValue::Number(value.into()))
+    }
+
+    fn visit_u128<E>(self, value: u128) -> Result<EntityValue, E>
+    where
+        E: serde::de::Error,
+    {
+        let de = serde::de::value::U128Deserializer::new(value);
+        Number::deserialize(de).map(EntityValue::Number)
+    }
+
+    #[inline]
+    fn visit_f64<E>(self, value: f64) -> Result<EntityValue, E> {
+        Ok(Number::from_f64(value).map_or(EntityValue::Null, EntityValue::Number))
+    }
+
+    #[inline]
+    fn visit_str<E>(self, value: &str) -> Result<EntityValue, E>
+    where
+        E: serde::de::Error,
+    {
+        self.visit_string(String::from(value))
+    }
+
+    #[inline]
+    fn visit_string<E>(self, value: String) -> Result<EntityValue, E>
+    where
+        E: serde::de::Error,
+    {
+        if !value.starts_with('~') {
+            // optimization to avoid all the checks for non-transit strings
+            return Ok(EntityValue::String(value));
+        }
+        if self.lenient {
+            Ok(EntityValue::from_transit(value.clone()).unwrap_or(EntityValue::String(value)))
+        } else {
+            EntityValue::from_transit(value).map_err(E::custom)
         }
-        deserializer.deserialize_any(EntityValueVisitor)
+    }
+
+    #[inline]
+    fn visit_none<E>(self) -> Result<EntityValue, E> {
+        Ok(EntityValue::Null)
+    }
+
+    #[inline]
+    fn visit_some<D>(self, deserializer: D) -> Result<EntityValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    #[inline]
+    fn visit_unit<E>(self) -> Result<EntityValue, E> {
+        Ok(EntityValue::Null)
+    }
+
+    #[inline]
+    fn visit_seq<V>(self, mut visitor: V) -> Result<EntityValue, V::Error>
+    where
+        V: SeqAccess<'de>,
+    {
+        let mut vec = Vec::new();
+
+        while let Some(elem) = (visitor.next_element_seed(self))? {
+            vec.push(elem);
+        }
+
+        Ok(EntityValue::Array(vec))
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> Result<EntityValue, V::Error>
+    where
+        V: MapAccess<'de>,
+    {
+        let mut values = BTreeMap::new();
+
+        while let Some(key) = (visitor.next_key::<String>())? {
+            let value = visitor.next_value_seed(self)?;
+            values.insert(key, value);
+        }
+
+        Ok(EntityValue::Object(values))
     }
 }
 
@@ -269,6 +441,7 @@ impl<'de> Deserialize<'de> for EntityValue {
 mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
+    use serde_json::json;
 
     #[test]
     fn uuid() {
@@ -280,7 +453,7 @@ mod tests {
     }
     #[test]
     fn decimal() {
-        let entity = EntityValue::Decimal(BigDecimalWrapper::parse("123.456"));
+        let entity = EntityValue::Decimal(BigDecimalWrapper::parse("123.456").unwrap());
         let serialized = serde_json::to_string(&entity).unwrap();
         assert_eq!(serialized, "\"~f123.456\"");
         let deserialized: EntityValue = serde_json::from_str(&serialized).unwrap();
@@ -307,7 +480,7 @@ mod tests {
 
     #[test]
     fn date() {
-        let entity = EntityValue::Date(Date::parse("2020-01-01"));
+        let entity = EntityValue::Date(Date::parse("2020-01-01").unwrap());
         let serialized = serde_json::to_string(&entity).unwrap();
         assert_eq!(serialized, "\"~t2020-01-01\"");
         let deserialized: EntityValue = serde_json::from_str(&serialized).unwrap();
@@ -316,13 +489,100 @@ mod tests {
 
     #[test]
     fn datetime() {
-        let entity = EntityValue::DateTime(DateTimeWrapper::parse("2014-07-08T09:10:11.0+0000"));
+        let entity = EntityValue::DateTime(DateTimeWrapper::parse("2014-07-08T09:10:11.0+0000").unwrap());
         let serialized = serde_json::to_string(&entity).unwrap();
         assert_eq!(serialized, "\"~t2014-07-08T09:10:11.000000000+0000\"");
         let deserialized: EntityValue = serde_json::from_str(&serialized).unwrap();
         assert_eq!(entity, deserialized);
     }
 
+    #[test]
+    fn malformed_transit() {
+        let errors = [
+            ("\"~:foo\"", "invalid NI '~:foo'"),
+            ("\"~b!!\"", "invalid bytes '~b!!'"),
+            ("\"~t2020-13-01\"", "invalid date '~t2020-13-01'"),
+            ("\"~t2020-01-01T25:00\"", "invalid datetime '~t2020-01-01T25:00'"),
+            ("\"~fabc\"", "invalid decimal '~fabc'"),
+            ("{\"a\": [\"~:foo\"]}", "invalid NI '~:foo'"),
+        ];
+        for (json, message) in errors {
+            let error = serde_json::from_str::<EntityValue>(json).unwrap_err();
+            assert!(error.to_string().starts_with(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn lenient() {
+        let Lenient(value) = serde_json::from_str(r#"{"a": ["~:foo", "~:foo:bar"]}"#).unwrap();
+        let expected = EntityValue::Object(BTreeMap::from([(
+            "a".to_owned(),
+            EntityValue::Array(vec![
+                EntityValue::String("~:foo".to_owned()),
+                EntityValue::NI(NI::new("foo", "bar")),
+            ]),
+        )]));
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn object_order() {
+        let value: EntityValue = r#"{"b": 1, "_id": "1", "a": {"d": 2, "c": 3}}"#.parse().unwrap();
+        let sorted = r#"{"_id":"1","a":{"c":3,"d":2},"b":1}"#;
+        assert_eq!(sorted, serde_json::to_string(&value).unwrap());
+
+        let entity: Entity = serde_json::from_str(r#"{"b": 1, "_id": "1", "a": 2}"#).unwrap();
+        assert_eq!(
+            r#"{"_deleted":false,"_filtered":false,"_hash":"","_id":"1","_previous":null,"_ts":0,"_updated":0,"a":2,"b":1}"#,
+            serde_json::to_string(&entity).unwrap()
+        );
+    }
+
+    #[test]
+    fn entity_values() {
+        let entity: Entity = serde_json::from_str(r#"{"_id": "1", "a": "~:foo:bar"}"#).unwrap();
+        let expected = Entity {
+            content: BTreeMap::from([("a".to_owned(), EntityValue::NI(NI::new("foo", "bar")))]),
+            ..Entity::new("1")
+        };
+        assert_eq!(expected, entity);
+
+        let value: EntityValue = json!({
+            "_id": "2",
+            "_deleted": true,
+            "_ts": 3,
+            "_updated": 4,
+            "_hash": "h",
+            "_previous": null,
+            "_parent": "1",
+            "a": 1
+        })
+        .into();
+        let entity = Entity::try_from(value.clone()).unwrap();
+        assert!(entity.deleted && !entity.filtered);
+        assert_eq!(
+            (3, 4, None),
+            (entity.timestamp, entity.updated, entity.previous)
+        );
+        assert_eq!(("h", 2), (entity.hash.as_str(), entity.content.len()));
+        let round_trip = Entity::try_from(EntityValue::from(entity.clone())).unwrap();
+        assert_eq!(entity, round_trip);
+
+        for (value, reason) in [
+            (json!("1"), "entities must be objects"),
+            (json!({"a": 1}), "entities must have an '_id'"),
+            (json!({"_id": 1}), "'_id' must be a string"),
+            (json!({"_id": "1", "_hash": 1}), "'_hash' must be a string"),
+            (json!({"_id": "1", "_ts": -1}), "'_ts' must be an integer"),
+        ] {
+            let value: EntityValue = value.into();
+            assert_eq!(
+                Error::InvalidEntity(value.clone(), reason),
+                Entity::try_from(value).unwrap_err()
+            );
+        }
+    }
+
     #[test]
     fn main() {
         fn current_time_in_millis() -> u128 {
@@ -341,7 +601,7 @@ mod tests {
             previous: None,
             deleted: false,
             timestamp: current_time_in_millis(),
-            content: HashMap::from([
+            content: BTreeMap::from([
                 ("string".to_owned(), EntityValue::String("value".to_owned())),
                 (
                     "uri".to_owned(),
@@ -358,16 +618,16 @@ mod tests {
                 ("null".to_owned(), EntityValue::Null),
                 ("boolean".to_owned(), EntityValue::Bool(true)),
                 ("bytes".to_owned(), EntityValue::Bytes(ByteWrapper::from_array(b"hello"))),
-                ("ni".to_owned(), EntityValue::NI(NI::new(&"foo", &"bar"))),
-                ("uuid".to_owned(), EntityValue::UUID(UUID::parse(&"1"))),
+                ("ni".to_owned(), EntityValue::NI(NI::new("foo", "bar"))),
+                ("uuid".to_owned(), EntityValue::UUID(UUID::parse("1"))),
                 ("empty_array".to_owned(), EntityValue::Array(vec![])),
                 (
                     "empty_object".to_owned(),
-                    EntityValue::Object(HashMap::new()),
+                    EntityValue::Object(BTreeMap::new()),
                 ),
                 (
                     "object_with_uri".to_owned(),
-                    EntityValue::Object(HashMap::from([(
+                    EntityValue::Object(BTreeMap::from([(
                         "uri".to_owned(),
                         EntityValue::URI(URI::parse("http://vg.no")),
                     )])),
@@ -3,8 +3,10 @@ use std::fmt::Display;
 
 use serde::Serialize;
 
+use crate::error::Error;
 
-#[derive(Debug, PartialEq, Clone)]
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct NI {
     namespace: String,
     identifier: String,
@@ -14,20 +16,19 @@ impl NI {
         value.starts_with("~:")
     }
     
-    pub(crate) fn deserialize(value: &str) -> Self {
+    pub(crate) fn deserialize(value: &str) -> Result<Self, Error> {
         let rest = &value[2..];
         if let Some(last_colon_index) = rest.rfind(':') {
-            Self {
+            Ok(Self {
                 namespace: rest[0..last_colon_index].to_owned(),
                 identifier: rest[last_colon_index + 1..].to_owned(),
-            }
+            })
         } else {
-            // TODO how to return sensible error
-            todo!()
+            Err(Error::InvalidNI(value.to_owned()))
         }
     }
     
-    pub(crate) fn new(namespace: &str, identifier: &str) -> Self {
+    pub fn new(namespace: &str, identifier: &str) -> Self {
         Self { namespace: namespace.to_owned(), identifier: identifier.to_owned() }
     }
 }
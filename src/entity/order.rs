@@ -0,0 +1,200 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use bigdecimal::BigDecimal;
+use serde_json::Number;
+
+use crate::entity::EntityValue;
+
+impl EntityValue {
+    /// Compares values the way DTL does. Values of different types are ordered by type: null,
+    /// booleans, numbers, strings, bytes, dates, UUIDs, URIs, NIs, lists and objects. Numbers
+    /// and decimals are compared by value, so `1`, `1.0` and `~f1` are the same, and a date
+    /// is the start of its day in UTC when it is compared with a datetime.
+    pub fn compare(&self, other: &EntityValue) -> Ordering {
+        order(self, other, false)
+    }
+}
+
+/// The order of `compare`, where values that are only the same by value, like `1` and `1.0`,
+/// are ordered by how they are represented to be consistent with `==`.
+impl Ord for EntityValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        order(self, other, true)
+    }
+}
+
+impl PartialOrd for EntityValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The rank of the type of the value, and of its variant for types with several of them.
+fn rank(value: &EntityValue) -> (u8, u8) {
+    match value {
+        EntityValue::Null => (0, 0),
+        EntityValue::Bool(_) => (1, 0),
+        EntityValue::Number(_) => (2, 0),
+        EntityValue::Decimal(_) => (2, 1),
+        EntityValue::String(_) => (3, 0),
+        EntityValue::Bytes(_) => (4, 0),
+        EntityValue::Date(_) => (5, 0),
+        EntityValue::DateTime(_) => (5, 1),
+        EntityValue::UUID(_) => (6, 0),
+        EntityValue::URI(_) => (7, 0),
+        EntityValue::NI(_) => (8, 0),
+        EntityValue::Array(_) => (9, 0),
+        EntityValue::Object(_) => (10, 0),
+    }
+}
+
+fn order(a: &EntityValue, b: &EntityValue, exact: bool) -> Ordering {
+    let ordering = rank(a).0.cmp(&rank(b).0).then_with(|| match (a, b) {
+        (EntityValue::Bool(a), EntityValue::Bool(b)) => a.cmp(b),
+        (EntityValue::Number(a), EntityValue::Number(b)) => compare_numbers(a, b),
+        (EntityValue::Number(a), EntityValue::Decimal(b)) => decimal(a).cmp(b.as_big_decimal()),
+        (EntityValue::Decimal(a), EntityValue::Number(b)) => a.as_big_decimal().cmp(&decimal(b)),
+        (EntityValue::Decimal(a), EntityValue::Decimal(b)) => a.cmp(b),
+        (EntityValue::String(a), EntityValue::String(b)) => a.cmp(b),
+        (EntityValue::Bytes(a), EntityValue::Bytes(b)) => a.cmp(b),
+        (EntityValue::Date(a), EntityValue::Date(b)) => a.cmp(b),
+        (EntityValue::Date(a), EntityValue::DateTime(b)) => a.start_of_day().cmp(b.as_date_time()),
+        (EntityValue::DateTime(a), EntityValue::Date(b)) => a.as_date_time().cmp(&b.start_of_day()),
+        (EntityValue::DateTime(a), EntityValue::DateTime(b)) => a.cmp(b),
+        (EntityValue::UUID(a), EntityValue::UUID(b)) => a.cmp(b),
+        (EntityValue::URI(a), EntityValue::URI(b)) => a.cmp(b),
+        (EntityValue::NI(a), EntityValue::NI(b)) => a.cmp(b),
+        (EntityValue::Array(a), EntityValue::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| order(a, b, exact))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        // properties are sorted by name, so they are compared in the same order
+        (EntityValue::Object(a), EntityValue::Object(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((a_name, a), (b_name, b))| a_name.cmp(b_name).then_with(|| order(a, b, exact)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => Ordering::Equal,
+    });
+    if !exact || ordering.is_ne() {
+        return ordering;
+    }
+    rank(a).1.cmp(&rank(b).1).then_with(|| match (a, b) {
+        // integers before floats, which the same number can be either of
+        (EntityValue::Number(a), EntityValue::Number(b)) => a.is_f64().cmp(&b.is_f64()),
+        _ => Ordering::Equal,
+    })
+}
+
+fn compare_numbers(a: &Number, b: &Number) -> Ordering {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        return a.cmp(&b);
+    }
+    if a.is_f64() && b.is_f64() {
+        return a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal);
+    }
+    // integers above i64::MAX or integers and floats, compared exactly
+    decimal(a).cmp(&decimal(b))
+}
+
+fn decimal(number: &Number) -> BigDecimal {
+    BigDecimal::from_str(&number.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn value(value: &str) -> EntityValue {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn compare() {
+        let same = |a: &str, b: &str| value(a).compare(&value(b));
+        assert_eq!(Ordering::Equal, same("1", "1.0"));
+        assert_eq!(Ordering::Equal, same("1", r#""~f1.00""#));
+        assert_eq!(Ordering::Less, same("0.1", r#""~f0.11""#));
+        assert_eq!(Ordering::Greater, same("18446744073709551615", "1.5"));
+        assert_eq!(Ordering::Less, same("-1", "18446744073709551615"));
+        assert_eq!(
+            Ordering::Equal,
+            same(
+                r#""~t2024-01-02""#,
+                r#""~t2024-01-02T00:00:00.000000000+00:00""#
+            )
+        );
+        assert_eq!(
+            Ordering::Less,
+            same(
+                r#""~t2024-01-02""#,
+                r#""~t2024-01-02T01:00:00.000000000+00:00""#
+            )
+        );
+        assert_eq!(
+            Ordering::Greater,
+            same(
+                r#""~t2024-01-02""#,
+                r#""~t2024-01-02T01:00:00.000000000+02:00""#
+            )
+        );
+        assert_eq!(Ordering::Less, same(r#""~:a:b""#, r#""~:a:c""#));
+        assert_eq!(Ordering::Less, same(r#""~:a:z""#, r#""~:b:a""#));
+        assert_eq!(
+            Ordering::Equal,
+            same("[1, {\"a\": 2}]", "[1.0, {\"a\": \"~f2\"}]")
+        );
+        assert_eq!(Ordering::Less, same("[1, 2]", "[1, 2, 0]"));
+        assert_eq!(Ordering::Less, same("{\"a\": 2}", "{\"b\": 1}"));
+    }
+
+    #[test]
+    fn sort() {
+        let mut values: Vec<EntityValue> = json!([
+            {"a": 1}, [1], "~:a:b", "~rhttp://a", "~uabc", "~t2024-01-01", "~bAQ==", "b", "a",
+            "~f1", 1.0, 1, true, false, null
+        ])
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.clone().into())
+        .collect();
+        values.sort();
+        assert_eq!(
+            json!([
+                null, false, true, 1, 1.0, "~f1", "a", "b", "~bAQ==", "~t2024-01-01", "~uabc",
+                "~rhttp://a", "~:a:b", [1], {"a": 1}
+            ]),
+            EntityValue::Array(values)
+        );
+    }
+
+    #[test]
+    fn consistent_with_eq() {
+        let values = [
+            "1",
+            "1.0",
+            "\"~f1\"",
+            "\"~f1.0\"",
+            "[1]",
+            "[1.0]",
+            "{\"a\": 1}",
+            "{\"a\": 1.0}",
+        ]
+        .map(value);
+        for a in &values {
+            for b in &values {
+                assert_eq!(a == b, a.cmp(b).is_eq(), "{:?} {:?}", a, b);
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+            }
+        }
+    }
+}
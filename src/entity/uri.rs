@@ -4,7 +4,8 @@ use std::fmt::Display;
 use serde::Serialize;
 
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct URI(String);
 impl URI {
     pub(crate) fn deserialize(value: &str) -> URI {
@@ -15,7 +16,7 @@ impl URI {
         value.starts_with("~r")
     }
     
-    pub(crate) fn parse(arg: &str) -> URI {
+    pub fn parse(arg: &str) -> URI {
         URI(arg.to_owned())
     }
 }
@@ -4,7 +4,8 @@ use std::fmt::Display;
 use serde::Serialize;
 
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct UUID(String);
 impl UUID {
     pub(crate) fn can_deserialize(value: &str) -> bool {
@@ -15,7 +16,7 @@ impl UUID {
         UUID(value[2..].to_owned())
     }
     
-    pub(crate) fn parse(arg: &str) -> UUID {
+    pub fn parse(arg: &str) -> UUID {
         UUID(arg.to_owned())
     }
 }
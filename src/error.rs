@@ -0,0 +1,50 @@
+use core::fmt;
+use std::fmt::Display;
+
+use crate::entity::EntityValue;
+
+/// Errors from decoding transit encoded values and from running transforms,
+/// each carrying the offending value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    InvalidNI(String),
+    InvalidBytes(String, base64::DecodeError),
+    InvalidDate(String, chrono::ParseError),
+    InvalidDateTime(String, chrono::ParseError),
+    InvalidDecimal(String, bigdecimal::ParseBigDecimalError),
+    /// A value passed to `create` that isn't an entity, with the reason.
+    InvalidEntity(EntityValue, &'static str),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidNI(value) => {
+                write!(
+                    f,
+                    "invalid NI '{}': expected '~:namespace:identifier'",
+                    value
+                )
+            }
+            Error::InvalidBytes(value, e) => write!(f, "invalid bytes '{}': {}", value, e),
+            Error::InvalidDate(value, e) => write!(f, "invalid date '{}': {}", value, e),
+            Error::InvalidDateTime(value, e) => write!(f, "invalid datetime '{}': {}", value, e),
+            Error::InvalidDecimal(value, e) => write!(f, "invalid decimal '{}': {}", value, e),
+            Error::InvalidEntity(value, reason) => {
+                let value = serde_json::to_string(value).unwrap_or_default();
+                write!(f, "invalid entity {}: {}", value, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidNI(_) | Error::InvalidEntity(..) => None,
+            Error::InvalidBytes(_, e) => Some(e),
+            Error::InvalidDate(_, e) | Error::InvalidDateTime(_, e) => Some(e),
+            Error::InvalidDecimal(_, e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use std::{borrow::Cow, cell::RefCell};
+
+use serde_json::Value;
+
+use crate::{
+    ast::{Expression, Function, Pattern, Property, Variable},
+    dtl::*,
+    entity::EntityValue,
+    parser::{parse_expression, DtlError},
+    rules::RuleSet,
+};
+
+/// The values a DTL expression is evaluated against: `_S`, `_T` and `_.`,
+/// while `_P` and `_R` are found by following the parent contexts.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    pub source: &'a EntityValue,
+    pub target: Option<&'a EntityValue>,
+    pub current: Option<&'a EntityValue>,
+    pub parent: Option<&'a Context<'a>>,
+    pub rules: Option<&'a RuleSet>,
+    pub trace: Option<&'a Trace>,
+}
+
+/// The values `trace` transforms and expressions saw, in the order they were evaluated.
+#[derive(Debug, Default)]
+pub struct Trace {
+    events: RefCell<Vec<TraceEvent>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    /// JSON pointer to the `trace`, starting with the rule name
+    pub pointer: String,
+    pub expression: String,
+    pub value: EntityValue,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.borrow().clone()
+    }
+
+    pub fn into_events(self) -> Vec<TraceEvent> {
+        self.events.into_inner()
+    }
+}
+
+impl<'a> Context<'a> {
+    pub fn new(source: &'a EntityValue) -> Self {
+        Context {
+            source,
+            target: None,
+            current: None,
+            parent: None,
+            rules: None,
+            trace: None,
+        }
+    }
+
+    pub fn with_target(self, target: &'a EntityValue) -> Self {
+        Context {
+            target: Some(target),
+            ..self
+        }
+    }
+
+    pub fn with_current(self, current: &'a EntityValue) -> Self {
+        Context {
+            current: Some(current),
+            ..self
+        }
+    }
+
+    /// Sets the parent context, the trace is shared with it unless one is set already.
+    pub fn with_parent(self, parent: &'a Context<'a>) -> Self {
+        Context {
+            parent: Some(parent),
+            trace: self.trace.or(parent.trace),
+            ..self
+        }
+    }
+
+    pub fn with_rules(self, rules: &'a RuleSet) -> Self {
+        Context {
+            rules: Some(rules),
+            ..self
+        }
+    }
+
+    pub fn with_trace(self, trace: &'a Trace) -> Self {
+        Context {
+            trace: Some(trace),
+            ..self
+        }
+    }
+
+    pub fn root(&self) -> &Context<'a> {
+        match self.parent {
+            Some(parent) => parent.root(),
+            None => self,
+        }
+    }
+}
+
+pub fn eval_str(expression: &str, context: &Context) -> Result<EntityValue, DtlError> {
+    let value: Value =
+        serde_json::from_str(expression).map_err(|e| DtlError::new("", e.to_string()))?;
+    Ok(eval(&parse_expression(&value)?, context))
+}
+
+pub fn eval(expression: &Expression, context: &Context) -> EntityValue {
+    match expression {
+        Expression::Literal(value) => value.clone(),
+        Expression::Variable(Variable::Source) => context.source.clone(),
+        Expression::Variable(Variable::Target) => {
+            context.target.cloned().unwrap_or(EntityValue::Null)
+        }
+        Expression::Variable(Variable::Current) => {
+            context.current.cloned().unwrap_or(EntityValue::Null)
+        }
+        Expression::Parent(value) => match context.parent {
+            Some(parent) => eval(value, parent),
+            None => EntityValue::Null,
+        },
+        Expression::Root(value) => eval(value, context.root()),
+        Expression::Path(segments, value) => path_segments(segments, &eval(value, context)).clone(),
+        Expression::Apply(rule, value) => match context.rules {
+            Some(rules) => apply(
+                |item| rules.apply_in(rule, item, context),
+                &eval(value, context),
+            ),
+            None => EntityValue::Array(vec![]),
+        },
+        Expression::Call(Function::Map, args) => match args.as_slice() {
+            [function, items] => map(
+                |item| eval(function, &context.with_current(item)),
+                &eval(items, context),
+            ),
+            _ => EntityValue::Null,
+        },
+        Expression::Call(function, args) if function.is_coercion() && args.len() == 2 => {
+            let default = |value: &EntityValue| eval(&args[0], &context.with_current(value));
+            coerce(*function, Some(&default), &eval(&args[1], context))
+        }
+        Expression::Call(function, args) => match args.as_slice() {
+            [Expression::Pattern(pattern), args @ ..] => {
+                let args: Vec<EntityValue> = args.iter().map(|arg| eval(arg, context)).collect();
+                call_pattern(*function, pattern, &args)
+            }
+            args => {
+                let args: Vec<EntityValue> = args.iter().map(|arg| eval(arg, context)).collect();
+                call(*function, &args)
+            }
+        },
+        Expression::Pattern(pattern) => EntityValue::String(pattern.as_str().to_owned()),
+        Expression::Trace(point, value) => trace(
+            &point.pointer,
+            &point.expression,
+            eval(value, context),
+            context,
+        ),
+    }
+}
+
+/// Records a traced value if the context has a trace, and passes it on.
+pub fn trace(
+    pointer: &str,
+    expression: &str,
+    value: EntityValue,
+    context: &Context,
+) -> EntityValue {
+    if let Some(trace) = context.trace {
+        trace.events.borrow_mut().push(TraceEvent {
+            pointer: pointer.to_owned(),
+            expression: expression.to_owned(),
+            value: value.clone(),
+        });
+    }
+    value
+}
+
+/// Finds the name of the property `add` and `default` set, if it evaluates to a string.
+pub fn property_name<'a>(property: &'a Property, context: &Context) -> Option<Cow<'a, str>> {
+    match property {
+        Property::Name(name) => Some(Cow::Borrowed(name)),
+        Property::Expression(expression) => match eval(expression, context) {
+            EntityValue::String(name) => Some(Cow::Owned(name)),
+            _ => None,
+        },
+    }
+}
+
+/// Calls a function whose arguments have already been evaluated.
+pub fn call(function: Function, args: &[EntityValue]) -> EntityValue {
+    resolve(function)(args)
+}
+
+/// Looks up the implementation of a function, taking its evaluated arguments.
+pub fn resolve(function: Function) -> fn(&[EntityValue]) -> EntityValue {
+    match function {
+        Function::Concat => |args| match args {
+            [parts] => concat(parts),
+            parts => concat(&list_literal(parts)),
+        },
+        Function::List => list_literal,
+        Function::Lower => |args| lower(arg(args, 0)),
+        Function::Upper => |args| upper(arg(args, 0)),
+        Function::Trim => |args| trim(arg(args, 0)),
+        // the strings are always the last argument, the ones before it are optional
+        Function::Strip => |args| strip(optional(args, 0), last(args)),
+        Function::LStrip => |args| lstrip(optional(args, 0), last(args)),
+        Function::RStrip => |args| rstrip(optional(args, 0), last(args)),
+        Function::Replace => |args| replace(arg(args, 0), arg(args, 1), arg(args, 2)),
+        Function::Substring => |args| substring(arg(args, 0), arg(args, 1), arg(args, 2)),
+        Function::Split => |args| split(arg(args, 0), arg(args, 1)),
+        Function::Join => |args| join(arg(args, 0), arg(args, 1)),
+        Function::Length => |args| length(arg(args, 0)),
+        Function::StartsWith => |args| startswith(arg(args, 0), arg(args, 1)),
+        Function::EndsWith => |args| endswith(arg(args, 0), arg(args, 1)),
+        Function::LJust => |args| ljust(arg(args, 0), optional(args, 1), last(args)),
+        Function::RJust => |args| rjust(arg(args, 0), optional(args, 1), last(args)),
+        Function::Plus => |args| plus(arg(args, 0), arg(args, 1)),
+        Function::Minus => |args| minus(arg(args, 0), arg(args, 1)),
+        Function::Multiply => |args| multiply(arg(args, 0), arg(args, 1)),
+        Function::Divide => |args| divide(arg(args, 0), arg(args, 1)),
+        Function::Mod => |args| modulo(arg(args, 0), arg(args, 1)),
+        Function::Pow => |args| pow(arg(args, 0), arg(args, 1)),
+        Function::Abs => |args| abs(arg(args, 0)),
+        Function::Floor => |args| floor(arg(args, 0)),
+        Function::Ceil => |args| ceil(arg(args, 0)),
+        Function::Round => |args| round(optional(args, 0), last(args)),
+        // like concat, several arguments are the same as a list of them
+        Function::Sum => |args| match args {
+            [values] => sum(values),
+            values => sum(&list_literal(values)),
+        },
+        Function::Min => |args| match args {
+            [values] => min(values),
+            values => min(&list_literal(values)),
+        },
+        Function::Max => |args| match args {
+            [values] => max(values),
+            values => max(&list_literal(values)),
+        },
+        Function::Boolean => |args| coerce_args(Function::Boolean, args),
+        Function::Decimal => |args| coerce_args(Function::Decimal, args),
+        Function::Float => |args| coerce_args(Function::Float, args),
+        Function::Integer => |args| coerce_args(Function::Integer, args),
+        Function::String => |args| coerce_args(Function::String, args),
+        Function::Matches => |args| matches(arg(args, 0), arg(args, 1)),
+        Function::Eq => |args| eq(arg(args, 0), arg(args, 1)),
+        Function::Neq => |args| neq(arg(args, 0), arg(args, 1)),
+        Function::Gt => |args| gt(arg(args, 0), arg(args, 1)),
+        Function::Gte => |args| gte(arg(args, 0), arg(args, 1)),
+        Function::Lt => |args| lt(arg(args, 0), arg(args, 1)),
+        Function::Lte => |args| lte(arg(args, 0), arg(args, 1)),
+        Function::And => and,
+        Function::Or => or,
+        Function::Not => |args| not(arg(args, 0)),
+        Function::In => |args| is_in(arg(args, 0), arg(args, 1)),
+        Function::IsNull => |args| is_null(arg(args, 0)),
+        Function::IsEmpty => |args| is_empty(arg(args, 0)),
+        Function::IsNotEmpty => |args| is_not_empty(arg(args, 0)),
+        Function::Regex => |args| compile_pattern(Function::Regex, args),
+        Function::RegexSub => |args| compile_pattern(Function::RegexSub, args),
+        // evaluated lazily by eval
+        Function::Map => |_| EntityValue::Null,
+    }
+}
+
+/// Calls a coercion function, with a default that is evaluated for each value it can't convert.
+pub fn coerce(function: Function, default: DefaultValue, source: &EntityValue) -> EntityValue {
+    match function {
+        Function::Boolean => boolean(default, source),
+        Function::Decimal => decimal(default, source),
+        Function::Float => float(default, source),
+        Function::Integer => integer(default, source),
+        Function::String => string(default, source),
+        function => call(function, std::slice::from_ref(source)),
+    }
+}
+
+/// A default that has already been evaluated is the same for every value.
+fn coerce_args(function: Function, args: &[EntityValue]) -> EntityValue {
+    match args {
+        [default, source] => coerce(function, Some(&|_| default.clone()), source),
+        args => coerce(function, None, last(args)),
+    }
+}
+
+/// Calls a function that takes a regular expression with a compiled pattern and the
+/// evaluated arguments after it.
+pub fn call_pattern(function: Function, pattern: &Pattern, args: &[EntityValue]) -> EntityValue {
+    match function {
+        Function::Regex => regex(pattern.regex(), optional(args, 0), last(args)),
+        Function::RegexSub => regex_sub(pattern.regex(), arg(args, 0), arg(args, 1)),
+        function => {
+            let pattern = EntityValue::String(pattern.as_str().to_owned());
+            call(function, &[&[pattern], args].concat())
+        }
+    }
+}
+
+/// Patterns that aren't literals are compiled every time they are used, invalid ones give null.
+fn compile_pattern(function: Function, args: &[EntityValue]) -> EntityValue {
+    match args.split_first() {
+        Some((EntityValue::String(pattern), args)) => match Pattern::new(pattern) {
+            Ok(pattern) => call_pattern(function, &pattern, args),
+            Err(_) => EntityValue::Null,
+        },
+        _ => EntityValue::Null,
+    }
+}
+
+fn arg(args: &[EntityValue], index: usize) -> &EntityValue {
+    args.get(index).unwrap_or(&EntityValue::Null)
+}
+
+/// An argument that is only given if it isn't the last one.
+fn optional(args: &[EntityValue], index: usize) -> &EntityValue {
+    if args.len() > index + 1 {
+        arg(args, index)
+    } else {
+        &EntityValue::Null
+    }
+}
+
+fn last(args: &[EntityValue]) -> &EntityValue {
+    args.last().unwrap_or(&EntityValue::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn eval_json(expression: &str, source: Value) -> EntityValue {
+        let source = source.into();
+        eval_str(expression, &Context::new(&source)).unwrap()
+    }
+
+    #[test]
+    fn test_eval_hello_world() {
+        let result = eval_json(
+            r#" ["concat", "wor", 1, ["concat", "l", ["lower", "_S.x.y"], null]] "#,
+            json!({"x": {"y": "D"}}),
+        );
+        assert_eq!(json!("world"), result);
+    }
+
+    #[test]
+    fn test_eval_map() {
+        let result = eval_json(
+            r#" ["map", ["upper", "_."], ["list", "a", "B", "c"]] "#,
+            json!({}),
+        );
+        assert_eq!(json!(["A", "B", "C"]), result);
+    }
+
+    #[test]
+    fn test_eval_string_functions() {
+        let source = json!({"names": [" Ola ", "Kari"], "csv": "a;b"});
+        let eval =
+            |expression: serde_json::Value| eval_json(&expression.to_string(), source.clone());
+        assert_eq!(json!(["Ola", "Kari"]), eval(json!(["strip", "_S.names"])));
+        assert_eq!(json!(["Ola ", "Kari"]), eval(json!(["lstrip", "_S.names"])));
+        assert_eq!(
+            json!(["Ol", "Kari"]),
+            eval(json!(["strip", " a", "_S.names"]))
+        );
+        assert_eq!(
+            json!(["---Ola-", "--Kari-"]),
+            eval(json!([
+                "ljust",
+                7,
+                "-",
+                ["rjust", 6, "-", ["trim", "_S.names"]]
+            ]))
+        );
+        assert_eq!(
+            json!(["x", "c"]),
+            eval(json!([
+                "split",
+                ";",
+                [
+                    "replace",
+                    "a",
+                    "c",
+                    ["substring", 0, 3, ["join", ";", ["list", "x", "_S.csv"]]]
+                ]
+            ]))
+        );
+        assert_eq!(
+            json!([false, true]),
+            eval(json!(["startswith", "K", "_S.names"]))
+        );
+        assert_eq!(
+            json!(["2", "3"]),
+            eval(json!([
+                "string",
+                ["list", ["length", "_S.names"], ["length", "_S.csv"]]
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_eval_patterns() {
+        let source = json!({"a": ["x-1", "y-22"], "pattern": "(.)-"});
+        let eval =
+            |expression: serde_json::Value| eval_json(&expression.to_string(), source.clone());
+        assert_eq!(json!(["1", "22"]), eval(json!(["regex", "\\d+", "_S.a"])));
+        assert_eq!(
+            json!(["x", "y"]),
+            eval(json!(["regex", "_S.pattern", "_S.a"]))
+        );
+        assert_eq!(json!(null), eval(json!(["regex", ["concat", "("], "_S.a"])));
+        assert_eq!(
+            json!(["1", "22"]),
+            eval(json!(["regex-sub", "_S.pattern", "", "_S.a"]))
+        );
+        assert_eq!(json!([false, true]), eval(json!(["matches", "y*", "_S.a"])));
+    }
+
+    #[test]
+    fn test_eval_arithmetic() {
+        let source = json!({"a": [1, 2.5, "~f0.5"], "b": 2});
+        let eval =
+            |expression: serde_json::Value| eval_json(&expression.to_string(), source.clone());
+        assert_eq!(
+            json!([2, 5.0, "~f1.0"]),
+            eval(json!([
+                "multiply",
+                "_S.b",
+                ["plus", "_S.a", ["minus", 1, 1]]
+            ]))
+        );
+        assert_eq!(json!("~f4.0"), eval(json!(["sum", "_S.a"])));
+        assert_eq!(json!(2.5), eval(json!(["max", "_S.b", 2.5, "~f1"])));
+        assert_eq!(json!(1), eval(json!(["mod", ["pow", "_S.b", 3], 7])));
+        assert_eq!(json!(3), eval(json!(["round", ["divide", 5, "_S.b"]])));
+    }
+
+    #[test]
+    fn test_eval_operators() {
+        let source = json!({"a": [1, "~f2"], "b": 2.0, "c": ""});
+        let eval =
+            |expression: serde_json::Value| eval_json(&expression.to_string(), source.clone());
+        assert_eq!(json!(true), eval(json!(["in", "_S.b", "_S.a"])));
+        assert_eq!(json!(true), eval(json!(["gt", "_S.a", ["list", 1]])));
+        assert_eq!(
+            json!(false),
+            eval(json!(["and", ["eq", "_S.b", 2], ["is-not-empty", "_S.c"]]))
+        );
+        assert_eq!(
+            json!(true),
+            eval(json!(["or", ["is-null", "_S.x"], ["not", "_S.b"]]))
+        );
+    }
+
+    #[test]
+    fn test_eval_variables() {
+        let source = json!({"a": "b"}).into();
+        let target = json!({"c": "d"}).into();
+        let context = Context::new(&source).with_target(&target);
+        assert_eq!(json!("b"), eval_str(r#" "_S.a" "#, &context).unwrap());
+        assert_eq!(json!("d"), eval_str(r#" "_T.c" "#, &context).unwrap());
+        assert_eq!(json!(null), eval_str(r#" "_." "#, &context).unwrap());
+        assert_eq!(json!(null), eval_str(r#" "_S.x.y" "#, &context).unwrap());
+    }
+
+    #[test]
+    fn test_eval_literals() {
+        assert_eq!(
+            json!(["~rhttp://www.example.org/", "True", false, 1234]),
+            eval_json(
+                r#" ["list", "~rhttp://www.example.org/", "True", false, 1234] "#,
+                json!({})
+            )
+        );
+    }
+}
@@ -1,7 +1,16 @@
+#[cfg(test)]
 use crate::{dtl::*, entity::EntityValue};
 
-mod dtl;
-mod entity;
+pub mod ast;
+pub mod compile;
+pub mod desugar;
+pub mod dtl;
+pub mod entity;
+pub mod error;
+pub mod eval;
+pub mod parser;
+pub mod rules;
+pub mod types;
 
 /*
 
@@ -25,6 +34,7 @@ mod entity;
 
 */
 
+#[cfg(test)]
 fn hello_world2(source: &EntityValue) -> Vec<EntityValue> {
     let mut target = Target::new();
     target.add(
@@ -56,18 +66,21 @@ fn hello_world2(source: &EntityValue) -> Vec<EntityValue> {
 
            foo:
            [
+             ["add", "_id", ["source"]],
              ["add", "bar", ["source"]]
            ]
 */
 
+#[cfg(test)]
 fn create_foo2(source: &EntityValue) -> Vec<EntityValue> {
-    let foo = |source: &EntityValue| {
+    let foo_rule = |source: &EntityValue| {
         let mut target = Target::new();
+        target.add("_id", source.clone());
         target.add("bar", source.clone());
         target.output()
     };
     let mut target = Target::new();
-    target.create(apply(foo, path(string_literal("foo"), source)));
+    target.create(apply(foo_rule, path(string_literal("foo"), source)));
     target.filter();
     target.output()
 }
@@ -82,12 +95,13 @@ fn create_foo2(source: &EntityValue) -> Vec<EntityValue> {
               ]
             ]
 */
+#[cfg(test)]
 fn map_upper2(_: &EntityValue) -> Vec<EntityValue> {
     let mut target = Target::new();
     target.add(
         "bar",
         map(
-            |s| upper(s),
+            upper,
             &EntityValue::Array(vec![
                 EntityValue::String("a".into()),
                 EntityValue::String("B".into()),
@@ -125,15 +139,47 @@ mod tests {
         })
         .into();
         let result = create_foo2(&source);
-        let expected1 = json!(
-            {"bar": "bar"}
-        );
-        let expected2 = json!(
-            {"bar": "baz"}
+        let expected1: EntityValue = json!(
+            {"_id": "bar", "bar": "bar"}
+        )
+        .into();
+        let expected2: EntityValue = json!(
+            {"_id": "baz", "bar": "baz"}
+        )
+        .into();
+        let expected3 = json!(
+            {"_filtered": true}
         );
-        assert_eq!(2, result.len());
+        assert_eq!(3, result.len());
         assert_eq!(expected1, result[0]);
         assert_eq!(expected2, result[1]);
+        assert_eq!(expected3, result[2]);
+    }
+
+    #[test]
+    fn test_create_foo_rule_set() {
+        let rules = rules::RuleSet::parse_str(
+            r#"
+            {
+              "default": [
+                ["create",
+                  ["apply", "foo", ["path", "foo", ["source"]]]
+                ],
+                ["filter"]
+              ],
+              "foo": [
+                ["add", "_id", ["source"]],
+                ["add", "bar", ["source"]]
+              ]
+            }
+            "#,
+        )
+        .unwrap();
+        let source = json!({
+            "foo": ["bar", "baz"]
+        })
+        .into();
+        assert_eq!(create_foo2(&source), rules.execute(&source));
     }
 
     #[test]
@@ -0,0 +1,712 @@
+use core::fmt;
+use std::fmt::Display;
+
+use serde_json::Value;
+
+use crate::{
+    ast::{Expression, Function, Pattern, Property, Rule, TracePoint, Transform, Variable},
+    desugar::{desugar, desugar_expression},
+    entity::EntityValue,
+};
+
+/// An error in a DTL document, located by a JSON pointer into that document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DtlError {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl DtlError {
+    pub(crate) fn new(pointer: &str, message: impl Into<String>) -> Self {
+        DtlError {
+            pointer: pointer.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for DtlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.pointer, self.message)
+    }
+}
+
+impl std::error::Error for DtlError {}
+
+/// Appends a reference token to a JSON pointer, escaping it as described in RFC 6901.
+pub(crate) fn pointer_child(pointer: &str, token: impl Display) -> String {
+    let token = token.to_string().replace('~', "~0").replace('/', "~1");
+    format!("{}/{}", pointer, token)
+}
+
+pub fn parse_str(rule: &str) -> Result<Rule, DtlError> {
+    let value: Value = serde_json::from_str(rule).map_err(|e| DtlError::new("", e.to_string()))?;
+    parse(&value)
+}
+
+/// Parses a DTL rule, accepting both the shorthand and the explicit notation.
+pub fn parse(rule: &Value) -> Result<Rule, DtlError> {
+    let mut parser = Parser::default();
+    let rule = parser.rule(&desugar(rule), "");
+    parser.finish(rule)
+}
+
+pub fn parse_expression(expression: &Value) -> Result<Expression, DtlError> {
+    let mut parser = Parser::default();
+    let expression = parser.expression(&desugar_expression(expression), "");
+    parser.finish(expression)
+}
+
+/// Parses desugared DTL, collecting every problem it finds instead of stopping at the first.
+#[derive(Debug, Default)]
+pub(crate) struct Parser {
+    pub(crate) errors: Vec<DtlError>,
+}
+
+impl Parser {
+    pub(crate) fn error(&mut self, pointer: &str, message: impl Into<String>) {
+        self.errors.push(DtlError::new(pointer, message));
+    }
+
+    pub(crate) fn finish<T>(self, parsed: T) -> Result<T, DtlError> {
+        match self.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(parsed),
+        }
+    }
+
+    pub(crate) fn rule(&mut self, rule: &Value, pointer: &str) -> Rule {
+        let Value::Array(transforms) = rule else {
+            self.error(pointer, "a rule must be a list of transforms");
+            return vec![];
+        };
+        if let Some(Value::String(_)) = transforms.first() {
+            self.error(
+                pointer,
+                "expected a list of transforms, not a single transform",
+            );
+            return vec![];
+        }
+        transforms
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| self.transform(t, &pointer_child(pointer, i)))
+            .collect()
+    }
+
+    fn transform(&mut self, transform: &Value, pointer: &str) -> Option<Transform> {
+        let (name, args) = self.split_call(transform, pointer)?;
+        let arg = |i: usize| pointer_child(pointer, i + 1);
+        match name {
+            "add" | "default" => {
+                self.expect_args(name, args, 2, Some(2), pointer)?;
+                let property = self.property(&args[0], &arg(0));
+                let value = self.expression(&args[1], &arg(1));
+                Some(if name == "add" {
+                    Transform::Add(property?, value)
+                } else {
+                    Transform::Default(property?, value)
+                })
+            }
+            "rename" => {
+                self.expect_args(name, args, 2, Some(2), pointer)?;
+                let from = self.name(&args[0], &arg(0));
+                let to = self.name(&args[1], &arg(1));
+                Some(Transform::Rename(from?, to?))
+            }
+            "create" => {
+                self.expect_args(name, args, 1, Some(2), pointer)?;
+                let value = self.expression(&args[0], &arg(0));
+                let id = args.get(1).map(|id| self.expression(id, &arg(1)));
+                Some(Transform::Create(value, id))
+            }
+            "merge" | "merge-union" => {
+                self.expect_args(name, args, 1, Some(1), pointer)?;
+                let value = self.expression(&args[0], &arg(0));
+                Some(if name == "merge" {
+                    Transform::Merge(value)
+                } else {
+                    Transform::MergeUnion(value)
+                })
+            }
+            "copy" | "remove" => {
+                self.expect_args(name, args, 1, Some(2), pointer)?;
+                let include = self.patterns(&args[0], &arg(0));
+                let exclude = match args.get(1) {
+                    Some(exclude) => self.patterns(exclude, &arg(1)),
+                    None => Some(vec![]),
+                };
+                let (include, exclude) = (include?, exclude?);
+                Some(if name == "copy" {
+                    Transform::Copy(include, exclude)
+                } else {
+                    Transform::Remove(include, exclude)
+                })
+            }
+            "filter" | "discard" => {
+                self.expect_args(name, args, 0, Some(1), pointer)?;
+                let condition = args.first().map(|c| self.expression(c, &arg(0)));
+                Some(if name == "filter" {
+                    Transform::Filter(condition)
+                } else {
+                    Transform::Discard(condition)
+                })
+            }
+            "if" => {
+                self.expect_args(name, args, 2, Some(3), pointer)?;
+                let condition = self.expression(&args[0], &arg(0));
+                let then = self.rule(&args[1], &arg(1));
+                let otherwise = match args.get(2) {
+                    Some(otherwise) => self.rule(otherwise, &arg(2)),
+                    None => vec![],
+                };
+                Some(Transform::If(condition, then, otherwise))
+            }
+            "case" => {
+                self.expect_args(name, args, 1, None, pointer)?;
+                let branches = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, branch)| match branch.as_array().map(Vec::as_slice) {
+                        Some([condition, rule]) => Some((
+                            self.expression(condition, &pointer_child(&arg(i), 0)),
+                            self.rule(rule, &pointer_child(&arg(i), 1)),
+                        )),
+                        _ => {
+                            self.error(&arg(i), "expected a condition and a list of transforms");
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Some(Transform::Case(
+                    branches.into_iter().collect::<Option<_>>()?,
+                ))
+            }
+            "comment" => Some(Transform::Comment),
+            "trace" => {
+                self.expect_args(name, args, 1, Some(1), pointer)?;
+                Some(Transform::Trace(self.trace(&args[0], pointer)))
+            }
+            _ => {
+                self.error(
+                    &pointer_child(pointer, 0),
+                    format!("unknown transform '{}'", name),
+                );
+                None
+            }
+        }
+    }
+
+    /// Parses the argument of the `trace` at `pointer`.
+    fn trace(&mut self, expression: &Value, pointer: &str) -> Expression {
+        let traced = self.expression(expression, &pointer_child(pointer, 1));
+        let point = TracePoint {
+            pointer: pointer.to_owned(),
+            expression: expression.to_string(),
+        };
+        Expression::Trace(point, Box::new(traced))
+    }
+
+    /// Parses an expression, a placeholder is returned for invalid expressions
+    /// so that the rest of the document can still be checked.
+    pub(crate) fn expression(&mut self, expression: &Value, pointer: &str) -> Expression {
+        self.try_expression(expression, pointer)
+            .unwrap_or(Expression::Literal(EntityValue::Null))
+    }
+
+    fn try_expression(&mut self, expression: &Value, pointer: &str) -> Option<Expression> {
+        let arg = |i: usize| pointer_child(pointer, i + 1);
+        let (name, args) = self.split_call(expression, pointer)?;
+        match name {
+            // errors point at the literal itself, its desugared form isn't in the original document
+            "string_literal" | "number_literal" | "boolean_literal" | "transit_literal" => {
+                self.expect_args(name, args, 1, Some(1), pointer)?;
+                let literal = match (name, &args[0]) {
+                    ("string_literal", Value::String(s)) => EntityValue::String(s.clone()),
+                    ("number_literal", Value::Number(n)) => EntityValue::Number(n.clone()),
+                    ("boolean_literal", Value::Bool(b)) => EntityValue::Bool(*b),
+                    ("transit_literal", v @ Value::String(s)) if s.starts_with('~') => {
+                        match serde_json::from_value(v.clone()) {
+                            Ok(literal) => literal,
+                            Err(e) => {
+                                self.error(pointer, e.to_string());
+                                return None;
+                            }
+                        }
+                    }
+                    _ => {
+                        self.error(pointer, format!("invalid argument to '{}'", name));
+                        return None;
+                    }
+                };
+                Some(Expression::Literal(literal))
+            }
+            "null_literal" => {
+                self.expect_args(name, args, 0, Some(0), pointer)?;
+                Some(Expression::Literal(EntityValue::Null))
+            }
+            "path" => {
+                self.expect_args(name, args, 1, None, pointer)?;
+                let (value, segments) = args.split_last()?;
+                let segments: Vec<Option<String>> = segments
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| self.name(s, &arg(i)))
+                    .collect();
+                let value = self.expression(value, &arg(args.len() - 1));
+                Some(Expression::Path(
+                    segments.into_iter().collect::<Option<_>>()?,
+                    Box::new(value),
+                ))
+            }
+            "parent" | "root" => {
+                self.expect_args(name, args, 1, Some(1), pointer)?;
+                let value = Box::new(self.expression(&args[0], &arg(0)));
+                Some(if name == "parent" {
+                    Expression::Parent(value)
+                } else {
+                    Expression::Root(value)
+                })
+            }
+            "apply" => {
+                self.expect_args(name, args, 2, Some(2), pointer)?;
+                let rule = self.name(&args[0], &arg(0));
+                let value = self.expression(&args[1], &arg(1));
+                Some(Expression::Apply(rule?, Box::new(value)))
+            }
+            "trace" => {
+                self.expect_args(name, args, 1, Some(1), pointer)?;
+                Some(self.trace(&args[0], pointer))
+            }
+            _ => {
+                if let Some(variable) = Variable::from_name(name) {
+                    self.expect_args(name, args, 0, Some(0), pointer)?;
+                    return Some(Expression::Variable(variable));
+                }
+                let Some(function) = Function::from_name(name) else {
+                    self.error(
+                        &pointer_child(pointer, 0),
+                        format!("unknown function '{}'", name),
+                    );
+                    return None;
+                };
+                let (min, max) = function.arity();
+                let valid = self.expect_args(name, args, min, max, pointer).is_some();
+                let args = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| {
+                        let expression = self.expression(a, &arg(i));
+                        if let Expression::Literal(literal) = &expression {
+                            if let Some(message) = function.check_literal(i, literal) {
+                                self.error(&arg(i), message);
+                            } else if let (true, EntityValue::String(pattern)) =
+                                (function.is_pattern(i), literal)
+                            {
+                                match Pattern::new(pattern) {
+                                    Ok(pattern) => return Expression::Pattern(pattern),
+                                    Err(e) => self.error(&arg(i), pattern_error(name, &e)),
+                                }
+                            }
+                        }
+                        expression
+                    })
+                    .collect();
+                valid.then_some(Expression::Call(function, args))
+            }
+        }
+    }
+
+    fn split_call<'a>(&mut self, call: &'a Value, pointer: &str) -> Option<(&'a str, &'a [Value])> {
+        let Value::Array(items) = call else {
+            self.error(pointer, "expected a list");
+            return None;
+        };
+        match items.split_first() {
+            Some((Value::String(name), args)) => Some((name, args)),
+            Some(_) => {
+                self.error(&pointer_child(pointer, 0), "expected a function name");
+                None
+            }
+            None => {
+                self.error(pointer, "expected a function name");
+                None
+            }
+        }
+    }
+
+    fn expect_args(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        min: usize,
+        max: Option<usize>,
+        pointer: &str,
+    ) -> Option<()> {
+        let count = args.len();
+        let expected = match max {
+            Some(max) if max == min => min.to_string(),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+        if count >= min && max.is_none_or(|max| count <= max) {
+            Some(())
+        } else {
+            self.error(
+                pointer,
+                format!("'{}' expects {} argument(s), got {}", name, expected, count),
+            );
+            None
+        }
+    }
+
+    fn property(&mut self, property: &Value, pointer: &str) -> Option<Property> {
+        match property {
+            Value::String(name) => Some(Property::Name(name.clone())),
+            Value::Array(_) => Some(Property::Expression(self.expression(property, pointer))),
+            _ => {
+                self.error(pointer, "expected a property name or an expression");
+                None
+            }
+        }
+    }
+
+    /// Parses a glob pattern, or a list of them.
+    fn patterns(&mut self, patterns: &Value, pointer: &str) -> Option<Vec<String>> {
+        match patterns {
+            Value::String(pattern) => Some(vec![pattern.clone()]),
+            Value::Array(patterns) => patterns
+                .iter()
+                .enumerate()
+                .map(|(i, pattern)| self.name(pattern, &pointer_child(pointer, i)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .collect(),
+            _ => {
+                self.error(pointer, "expected a pattern or a list of patterns");
+                None
+            }
+        }
+    }
+
+    fn name(&mut self, name: &Value, pointer: &str) -> Option<String> {
+        match name {
+            Value::String(s) => Some(s.clone()),
+            _ => {
+                self.error(pointer, "expected a string");
+                None
+            }
+        }
+    }
+}
+
+/// The last line of a regex error says what is wrong, the lines before it repeat the pattern.
+fn pattern_error(name: &str, error: &regex::Error) -> String {
+    let message = error.to_string();
+    let reason = message.lines().last().unwrap_or_default().trim();
+    let reason = reason.strip_prefix("error: ").unwrap_or(reason);
+    format!("'{}' expects a regular expression: {}", name, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn literal(value: Value) -> Expression {
+        Expression::Literal(value.into())
+    }
+
+    #[test]
+    fn test_parse_hello_world() {
+        let rule = parse_str(
+            r#"
+            [
+              ["add", "hello",
+                ["concat", ["string_literal", "wor"], ["number_literal", 1],
+                  ["concat", ["string_literal", "l"], ["lower", ["path", "x", "y", ["source"]]], ["null_literal"]]
+                ]
+              ]
+            ]
+            "#,
+        )
+        .unwrap();
+        let expected = vec![Transform::Add(
+            "hello".into(),
+            Expression::Call(
+                Function::Concat,
+                vec![
+                    literal(json!("wor")),
+                    literal(json!(1)),
+                    Expression::Call(
+                        Function::Concat,
+                        vec![
+                            literal(json!("l")),
+                            Expression::Call(
+                                Function::Lower,
+                                vec![Expression::Path(
+                                    vec!["x".into(), "y".into()],
+                                    Box::new(Expression::Variable(Variable::Source)),
+                                )],
+                            ),
+                            literal(json!(null)),
+                        ],
+                    ),
+                ],
+            ),
+        )];
+        assert_eq!(expected, rule);
+    }
+
+    #[test]
+    fn test_parse_create_foo() {
+        let rule = parse(&json!([
+            ["create", ["apply", "foo", ["path", "foo", ["source"]]]],
+            ["create", ["source"], ["path", "id", ["current"]]],
+            ["filter"]
+        ]))
+        .unwrap();
+        let expected = vec![
+            Transform::Create(
+                Expression::Apply(
+                    "foo".into(),
+                    Box::new(Expression::Path(
+                        vec!["foo".into()],
+                        Box::new(Expression::Variable(Variable::Source)),
+                    )),
+                ),
+                None,
+            ),
+            Transform::Create(
+                Expression::Variable(Variable::Source),
+                Some(Expression::Path(
+                    vec!["id".into()],
+                    Box::new(Expression::Variable(Variable::Current)),
+                )),
+            ),
+            Transform::Filter(None),
+        ];
+        assert_eq!(expected, rule);
+    }
+
+    #[test]
+    fn test_parse_shorthand() {
+        let shorthand = parse(&json!([[
+            "add",
+            "hello",
+            [
+                "concat",
+                "wor",
+                1,
+                ["concat", "l", ["lower", "_S.x.y"], null]
+            ]
+        ]]))
+        .unwrap();
+        let explicit = parse(&json!([[
+            "add",
+            "hello",
+            [
+                "concat",
+                ["string_literal", "wor"],
+                ["number_literal", 1],
+                [
+                    "concat",
+                    ["string_literal", "l"],
+                    ["lower", ["path", "x", "y", ["source"]]],
+                    ["null_literal"]
+                ]
+            ]
+        ]]))
+        .unwrap();
+        assert_eq!(explicit, shorthand);
+        assert_eq!(
+            literal(json!("~rhttp://www.example.org/")),
+            parse_expression(&json!("~rhttp://www.example.org/")).unwrap()
+        );
+        assert_eq!(
+            Expression::Literal(EntityValue::String("~rfoo".into())),
+            parse_expression(&json!(["string_literal", "~rfoo"])).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            DtlError::new("/0/2/0", "unknown function 'lowr'"),
+            parse(&json!([["add", "a", ["lowr", "b"]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/1", "'add' expects 2 argument(s), got 1"),
+            parse(&json!([["filter"], ["add", "a"]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/1", "expected a property name or an expression"),
+            parse(&json!([["add", 1, "b"]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new(
+                "/0/2/1",
+                "invalid NI '~:foo': expected '~:namespace:identifier'"
+            ),
+            parse(&json!([["add", "a", ["lower", "~:foo"]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/2/1", "expected a string"),
+            parse(&json!([["copy", "*", ["_*", 1]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/2/2", "'substring' expects a number"),
+            parse(&json!([["add", "a", ["substring", 0, "1", "_S.a"]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/2/1", "'split' expects a string"),
+            parse(&json!([["add", "a", ["split", 1, "_S.a"]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/2/2", "'plus' expects a number"),
+            parse(&json!([["add", "a", ["plus", 1, "1"]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new(
+                "/0/2/1",
+                "'regex' expects a regular expression: unclosed group"
+            ),
+            parse(&json!([["add", "a", ["regex", "(a", "_S.a"]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/2", "'ljust' expects 2 to 3 argument(s), got 1"),
+            parse(&json!([["add", "a", ["ljust", "_S.a"]]])).unwrap_err()
+        );
+        assert_eq!("", parse_str("[").unwrap_err().pointer);
+    }
+
+    #[test]
+    fn test_parse_copy_and_remove() {
+        let rule = parse(&json!([["copy", "*", ["_*", "tmp"]], ["remove", "tmp_*"]])).unwrap();
+        let expected = vec![
+            Transform::Copy(vec!["*".into()], vec!["_*".into(), "tmp".into()]),
+            Transform::Remove(vec!["tmp_*".into()], vec![]),
+        ];
+        assert_eq!(expected, rule);
+    }
+
+    #[test]
+    fn test_parse_filter_and_discard() {
+        let rule = parse(&json!([["filter", "_S.a"], ["discard"]])).unwrap();
+        let expected = vec![
+            Transform::Filter(Some(Expression::Path(
+                vec!["a".into()],
+                Box::new(Expression::Variable(Variable::Source)),
+            ))),
+            Transform::Discard(None),
+        ];
+        assert_eq!(expected, rule);
+    }
+
+    #[test]
+    fn test_parse_comment_and_trace() {
+        let rule = parse(&json!([
+            ["comment", "copies _S.a", ["apply", "nothing", "_S"]],
+            ["add", "a", ["trace", "_S.a"]],
+            ["trace", "_T"]
+        ]))
+        .unwrap();
+        let expected = vec![
+            Transform::Comment,
+            Transform::Add(
+                "a".into(),
+                Expression::Trace(
+                    TracePoint {
+                        pointer: "/1/2".into(),
+                        expression: r#"["path","a",["source"]]"#.into(),
+                    },
+                    Box::new(Expression::Path(
+                        vec!["a".into()],
+                        Box::new(Expression::Variable(Variable::Source)),
+                    )),
+                ),
+            ),
+            Transform::Trace(Expression::Trace(
+                TracePoint {
+                    pointer: "/2".into(),
+                    expression: r#"["target"]"#.into(),
+                },
+                Box::new(Expression::Variable(Variable::Target)),
+            )),
+        ];
+        assert_eq!(expected, rule);
+    }
+
+    #[test]
+    fn test_parse_patterns() {
+        let source = || Expression::Variable(Variable::Source);
+        assert_eq!(
+            Expression::Call(
+                Function::RegexSub,
+                vec![
+                    Expression::Pattern(Pattern::new("a+").unwrap()),
+                    Expression::Literal(EntityValue::String("b".into())),
+                    source(),
+                ]
+            ),
+            parse_expression(&json!(["regex-sub", "a+", "b", "_S"])).unwrap()
+        );
+        assert_eq!(
+            Expression::Call(Function::Regex, vec![source(), source()]),
+            parse_expression(&json!(["regex", "_S", "_S"])).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_conditions() {
+        let rule = parse(&json!([
+            ["if", "_S.a", [["add", "a", 1]], [["filter"]]],
+            ["case", ["_S.b", [["add", "b", 1]]], [true, []]]
+        ]))
+        .unwrap();
+        let source = || {
+            Expression::Path(
+                vec!["a".into()],
+                Box::new(Expression::Variable(Variable::Source)),
+            )
+        };
+        let expected = vec![
+            Transform::If(
+                source(),
+                vec![Transform::Add("a".into(), literal(json!(1)))],
+                vec![Transform::Filter(None)],
+            ),
+            Transform::Case(vec![
+                (
+                    Expression::Path(
+                        vec!["b".into()],
+                        Box::new(Expression::Variable(Variable::Source)),
+                    ),
+                    vec![Transform::Add("b".into(), literal(json!(1)))],
+                ),
+                (literal(json!(true)), vec![]),
+            ]),
+        ];
+        assert_eq!(expected, rule);
+        assert_eq!(
+            DtlError::new(
+                "/0/2",
+                "expected a list of transforms, not a single transform"
+            ),
+            parse(&json!([["if", true, ["add", "a", 1]]])).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/0/1", "expected a condition and a list of transforms"),
+            parse(&json!([["case", [true]]])).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_pointer_escaping() {
+        assert_eq!(
+            "/a~1b/c~0d",
+            pointer_child(&pointer_child("", "a/b"), "c~d")
+        );
+    }
+}
@@ -0,0 +1,752 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::{
+    ast::{Expression, Property, Rule, Transform},
+    desugar::desugar,
+    dtl::{is_true, set_ids, Target},
+    entity::{Entity, EntityValue},
+    error::Error,
+    eval::{eval, property_name, Context, Trace},
+    parser::{pointer_child, DtlError, Parser},
+};
+
+/// The rule executed for every source entity.
+pub const DEFAULT_RULE: &str = "default";
+
+/// A set of named DTL rules, e.g. `{"default": [...], "foo": [...]}`, where
+/// `["apply", "foo", ...]` refers to a sibling rule by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleSet {
+    rules: HashMap<String, Rule>,
+}
+
+impl RuleSet {
+    pub fn parse_str(rules: &str) -> Result<RuleSet, DtlError> {
+        let value: Value =
+            serde_json::from_str(rules).map_err(|e| DtlError::new("", e.to_string()))?;
+        Self::parse(&value)
+    }
+
+    /// Parses a rule set and checks that every applied rule exists and that no
+    /// rule ends up applying itself. Both the bare rule set and a transform
+    /// configuration with the rule set in `"rules"` are accepted.
+    pub fn parse(document: &Value) -> Result<RuleSet, DtlError> {
+        let mut parser = Parser::default();
+        let rules = Self::parse_document(document, &mut parser);
+        parser.finish(rules)
+    }
+
+    /// Checks a DTL document without running it, returns every problem found.
+    pub fn validate(document: &Value) -> Vec<DtlError> {
+        let mut parser = Parser::default();
+        Self::parse_document(document, &mut parser);
+        parser.errors
+    }
+
+    pub fn validate_str(document: &str) -> Vec<DtlError> {
+        match serde_json::from_str(document) {
+            Ok(document) => Self::validate(&document),
+            Err(e) => vec![DtlError::new("", e.to_string())],
+        }
+    }
+
+    fn parse_document(document: &Value, parser: &mut Parser) -> RuleSet {
+        match document.get("rules") {
+            Some(rules @ Value::Object(_)) => Self::parse_rules(rules, "/rules", parser),
+            _ => Self::parse_rules(document, "", parser),
+        }
+    }
+
+    fn parse_rules(rules: &Value, pointer: &str, parser: &mut Parser) -> RuleSet {
+        let mut parsed = HashMap::new();
+        let Value::Object(named) = rules else {
+            parser.error(pointer, "a rule set must be an object of named rules");
+            return RuleSet { rules: parsed };
+        };
+        if !named.contains_key(DEFAULT_RULE) {
+            parser.error(pointer, format!("missing '{}' rule", DEFAULT_RULE));
+        }
+        let mut applies = HashMap::new();
+        for (name, rule) in named {
+            let rule_pointer = pointer_child(pointer, name);
+            let rule = desugar(rule);
+            let mut references = Vec::new();
+            find_applies(&rule, &rule_pointer, &mut references);
+            for (reference, reference_pointer) in &references {
+                if !named.contains_key(reference) {
+                    parser.error(reference_pointer, format!("unknown rule '{}'", reference));
+                }
+            }
+            parsed.insert(name.clone(), parser.rule(&rule, &rule_pointer));
+            applies.insert(name.as_str(), references);
+        }
+        let mut recursive = Vec::new();
+        for name in named.keys() {
+            check_recursion(name, &applies, &mut vec![name.as_str()], &mut recursive);
+        }
+        for error in recursive {
+            if !parser.errors.contains(&error) {
+                parser.errors.push(error);
+            }
+        }
+        RuleSet { rules: parsed }
+    }
+
+    pub fn rule(&self, name: &str) -> Option<&Rule> {
+        self.rules.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Rule)> {
+        self.rules.iter().map(|(name, rule)| (name.as_str(), rule))
+    }
+
+    /// Runs the default rule against a source entity.
+    pub fn execute(&self, source: &EntityValue) -> Vec<EntityValue> {
+        self.apply(DEFAULT_RULE, source)
+    }
+
+    /// Runs the default rule against an entity, see `Target::into_entities`.
+    pub fn transform(&self, source: &Entity) -> Result<Vec<Entity>, Error> {
+        let value = source.clone().into();
+        match self.rules.get(DEFAULT_RULE) {
+            Some(rule) => {
+                run_rule(rule, &Context::new(&value).with_rules(self)).into_entities(source)
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Like `execute`, but records the values of `trace` transforms and expressions.
+    pub fn execute_traced(&self, source: &EntityValue, trace: &Trace) -> Vec<EntityValue> {
+        match self.rules.get(DEFAULT_RULE) {
+            Some(rule) => execute_rule(
+                rule,
+                &Context::new(source).with_rules(self).with_trace(trace),
+            ),
+            None => vec![],
+        }
+    }
+
+    /// Like `execute`, but fails if the default rule creates something that isn't an entity.
+    pub fn try_execute(&self, source: &EntityValue) -> Result<Vec<EntityValue>, Error> {
+        match self.rules.get(DEFAULT_RULE) {
+            Some(rule) => run_rule(rule, &Context::new(source).with_rules(self)).finish(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Runs a named rule against a source entity, returns nothing if the rule doesn't exist.
+    pub fn apply(&self, name: &str, source: &EntityValue) -> Vec<EntityValue> {
+        match self.rules.get(name) {
+            Some(rule) => execute_rule(rule, &Context::new(source).with_rules(self)),
+            None => vec![],
+        }
+    }
+
+    /// Runs a named rule from within another rule, which becomes the parent context.
+    pub fn apply_in(&self, name: &str, source: &EntityValue, parent: &Context) -> Vec<EntityValue> {
+        match self.rules.get(name) {
+            Some(rule) => execute_rule(
+                rule,
+                &Context::new(source).with_rules(self).with_parent(parent),
+            ),
+            None => vec![],
+        }
+    }
+}
+
+pub fn execute_rule(rule: &Rule, context: &Context) -> Vec<EntityValue> {
+    run_rule(rule, context).output()
+}
+
+fn run_rule(rule: &Rule, context: &Context) -> Target {
+    let mut target = Target::new();
+    execute_transforms(rule, context, &mut target);
+    target
+}
+
+fn execute_transforms(rule: &Rule, context: &Context, target: &mut Target) {
+    for transform in rule {
+        match transform {
+            Transform::Add(property, expression) => {
+                set(target, Target::add, property, expression, context)
+            }
+            Transform::Default(property, expression) => {
+                set(target, Target::set_default, property, expression, context)
+            }
+            Transform::Rename(from, to) => target.rename(from, to),
+            Transform::Create(expression, id) => {
+                let value = {
+                    let context = context.with_target(target.entity());
+                    let value = eval(expression, &context);
+                    match id {
+                        Some(id) => {
+                            set_ids(value, |entity| eval(id, &context.with_current(entity)))
+                        }
+                        None => value,
+                    }
+                };
+                target.create(value);
+            }
+            Transform::Merge(expression) => update(target, Target::merge, expression, context),
+            Transform::MergeUnion(expression) => {
+                update(target, Target::merge_union, expression, context)
+            }
+            Transform::Copy(include, exclude) => target.copy(include, exclude, context.source),
+            Transform::Remove(include, exclude) => target.remove(include, exclude),
+            Transform::Filter(condition) => {
+                if holds(condition, context, target) {
+                    target.filter();
+                }
+            }
+            Transform::Discard(condition) => {
+                if holds(condition, context, target) {
+                    target.discard();
+                }
+            }
+            Transform::If(condition, then, otherwise) => {
+                if is_true(&eval(condition, &context.with_target(target.entity()))) {
+                    execute_transforms(then, context, target);
+                } else {
+                    execute_transforms(otherwise, context, target);
+                }
+            }
+            Transform::Case(branches) => {
+                let branch = branches.iter().find(|(condition, _)| {
+                    is_true(&eval(condition, &context.with_target(target.entity())))
+                });
+                if let Some((_, rule)) = branch {
+                    execute_transforms(rule, context, target);
+                }
+            }
+            Transform::Comment => {}
+            Transform::Trace(expression) => {
+                eval(expression, &context.with_target(target.entity()));
+            }
+        }
+    }
+}
+
+/// Whether the optional condition of a transform holds, transforms without one always run.
+fn holds(condition: &Option<Expression>, context: &Context, target: &Target) -> bool {
+    match condition {
+        Some(condition) => is_true(&eval(condition, &context.with_target(target.entity()))),
+        None => true,
+    }
+}
+
+fn update(
+    target: &mut Target,
+    updater: fn(&mut Target, EntityValue),
+    expression: &Expression,
+    context: &Context,
+) {
+    let value = eval(expression, &context.with_target(target.entity()));
+    updater(target, value);
+}
+
+fn set(
+    target: &mut Target,
+    setter: fn(&mut Target, &str, EntityValue),
+    property: &Property,
+    expression: &Expression,
+    context: &Context,
+) {
+    let context = context.with_target(target.entity());
+    let name = property_name(property, &context);
+    let value = eval(expression, &context);
+    if let Some(name) = name {
+        setter(target, &name, value);
+    }
+}
+
+/// Collects the names of all rules applied in a desugared rule, with pointers to the names.
+fn find_applies(value: &Value, pointer: &str, references: &mut Vec<(String, String)>) {
+    if let Value::Array(items) = value {
+        if let Some(Value::String(name)) = items.first() {
+            if name == "comment" {
+                return;
+            }
+        }
+        if let [Value::String(function), Value::String(rule), ..] = items.as_slice() {
+            if function == "apply" {
+                references.push((rule.clone(), pointer_child(pointer, 1)));
+            }
+        }
+        for (i, item) in items.iter().enumerate() {
+            find_applies(item, &pointer_child(pointer, i), references);
+        }
+    }
+}
+
+fn check_recursion<'a>(
+    name: &str,
+    applies: &'a HashMap<&str, Vec<(String, String)>>,
+    stack: &mut Vec<&'a str>,
+    errors: &mut Vec<DtlError>,
+) {
+    for (reference, pointer) in applies.get(name).into_iter().flatten() {
+        if stack.contains(&reference.as_str()) {
+            let first = stack.iter().position(|name| name == reference).unwrap_or(0);
+            errors.push(DtlError::new(
+                pointer,
+                format!(
+                    "rule '{}' is applied recursively ({} -> {})",
+                    reference,
+                    stack[first..].join(" -> "),
+                    reference
+                ),
+            ));
+            continue;
+        }
+        stack.push(reference);
+        check_recursion(reference, applies, stack, errors);
+        stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::TraceEvent;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_by_name() {
+        let rules = RuleSet::parse_str(
+            r#"
+            {
+              "default": [
+                ["create",
+                  ["apply", "child", "_S.foo"]
+                ],
+                ["add", "single", ["apply", "foo", "_S.single"]],
+                ["filter"]
+              ],
+              "foo": [
+                ["add", "bar", "_S.name"]
+              ],
+              "child": [
+                ["add", "_id", "_S"],
+                ["add", "bar", "_S"]
+              ]
+            }
+            "#,
+        )
+        .unwrap();
+        let source = json!({
+            "foo": ["bar", "baz"],
+            "single": {"name": "a"}
+        })
+        .into();
+        let result = rules.execute(&source);
+        assert_eq!(3, result.len());
+        let expected: EntityValue = json!({"_id": "bar", "bar": "bar"}).into();
+        assert_eq!(expected, result[0]);
+        let expected: EntityValue = json!({"_id": "baz", "bar": "baz"}).into();
+        assert_eq!(expected, result[1]);
+        let filtered: EntityValue = json!({"single": {"bar": "a"}, "_filtered": true}).into();
+        assert_eq!(filtered, result[2]);
+
+        let rules = RuleSet::parse(&json!({
+            "default": [["add", "single", ["apply", "foo", "_S.single"]]],
+            "foo": [["add", "bar", "_S.a"]]
+        }))
+        .unwrap();
+        let result = rules.execute(&json!({"single": {"a": 1}}).into());
+        assert_eq!(json!({"single": {"bar": 1}}), result[0]);
+    }
+
+    #[test]
+    fn test_scopes() {
+        let rules = RuleSet::parse_str(
+            r#"
+            {
+              "default": [
+                ["add", "name", "_S.name"],
+                ["add", "greeting", ["concat", "hello ", "_T.name"]],
+                ["add", "children", ["apply", "child", "_S.children"]]
+              ],
+              "child": [
+                ["add", "name", "_S.name"],
+                ["add", "parent", "_P._S.name"],
+                ["add", "parent_greeting", "_P._T.greeting"],
+                ["add", "grandchildren", ["apply", "grandchild", "_S.children"]]
+              ],
+              "grandchild": [
+                ["add", "name", "_S.name"],
+                ["add", "parent", "_P._S.name"],
+                ["add", "grandparent", "_P._P._S.name"],
+                ["add", "root", "_R._S.name"],
+                ["add", "names", ["map", ["concat", "_.", "_T.name"], "_R._S.tags"]]
+              ]
+            }
+            "#,
+        )
+        .unwrap();
+        let source = json!({
+            "name": "a",
+            "tags": ["x", "y"],
+            "children": [
+                {"name": "b", "children": [{"name": "c"}]}
+            ]
+        })
+        .into();
+        let expected: EntityValue = json!({
+            "name": "a",
+            "greeting": "hello a",
+            "children": [{
+                "name": "b",
+                "parent": "a",
+                "parent_greeting": "hello a",
+                "grandchildren": [{
+                    "name": "c",
+                    "parent": "b",
+                    "grandparent": "a",
+                    "root": "a",
+                    "names": ["xc", "yc"]
+                }]
+            }]
+        })
+        .into();
+        assert_eq!(vec![expected], rules.execute(&source));
+    }
+
+    #[test]
+    fn test_dynamic_properties() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["add", ["concat", "x_", "_S.key"], "_S.value"],
+                ["add", "_S.key", 1],
+                ["add", ["list", "a"], 2],
+                ["default", "name", "_S.name"],
+                ["default", "name", "fallback"],
+                ["default", "other", "fallback"],
+                ["rename", "x_a", "renamed"]
+            ]
+        }))
+        .unwrap();
+        let source = json!({"key": "a", "value": "b", "name": "c"}).into();
+        let expected: EntityValue = json!({
+            "renamed": "b",
+            "_S.key": 1,
+            "name": "c",
+            "other": "fallback"
+        })
+        .into();
+        assert_eq!(vec![expected], rules.execute(&source));
+    }
+
+    #[test]
+    fn test_merge() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["add", "name", "_S.name"],
+                ["merge", ["apply", "hop", "_S.orders"]],
+                ["merge-union", ["apply", "hop", "_S.orders"]]
+            ],
+            "hop": [
+                ["add", "order", "_S.id"]
+            ]
+        }))
+        .unwrap();
+        let source = json!({"name": "a", "orders": [{"id": 1}, {"id": 2}]}).into();
+        let expected: EntityValue = json!({"name": "a", "order": [2, 1]}).into();
+        assert_eq!(vec![expected], rules.execute(&source));
+    }
+
+    #[test]
+    fn test_create() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["copy", "*"],
+                ["create", ["apply", "child", "_S.children"], ["concat", "_S._id", "-", "_.name"]],
+                ["create", "_S.extra"]
+            ],
+            "child": [
+                ["add", "name", "_S.name"]
+            ]
+        }))
+        .unwrap();
+        let source: EntityValue = json!({
+            "_id": "1",
+            "children": [{"name": "a"}, {"name": "b"}],
+            "extra": {"_id": "2"}
+        })
+        .into();
+        let expected: Vec<EntityValue> = vec![
+            json!({"_id": "1-a", "_parent": "1", "name": "a"}).into(),
+            json!({"_id": "1-b", "_parent": "1", "name": "b"}).into(),
+            json!({"_id": "2", "_parent": "1"}).into(),
+            source.clone(),
+        ];
+        assert_eq!(expected, rules.try_execute(&source).unwrap());
+
+        let source = json!({"_id": "1", "extra": ["bar", {"_id": "2"}]}).into();
+        assert_eq!(
+            "invalid entity \"bar\": created entities must be objects",
+            rules.try_execute(&source).unwrap_err().to_string()
+        );
+        let result = rules.execute(&source);
+        assert_eq!(2, result.len());
+        let expected: EntityValue = json!({"_id": "2", "_parent": "1"}).into();
+        assert_eq!(expected, result[0]);
+    }
+
+    #[test]
+    fn test_filter_and_discard() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["add", "type", "_S.type"],
+                ["filter", ["concat", "_T.type", "x"]],
+                ["discard", "_S.discard"],
+                ["add", "children", ["apply", "child", "_S.children"]]
+            ],
+            "child": [
+                ["add", "name", "_S.name"],
+                ["filter", "_S.filter"]
+            ]
+        }))
+        .unwrap();
+        let source = json!({
+            "children": [{"name": "a"}, {"name": "b", "filter": true}]
+        })
+        .into();
+        let expected: EntityValue = json!({"type": null, "children": [{"name": "a"}]}).into();
+        assert_eq!(vec![expected], rules.execute(&source));
+        assert_eq!(
+            Vec::<EntityValue>::new(),
+            rules.execute(&json!({"discard": [false, true]}).into())
+        );
+    }
+
+    #[test]
+    fn test_transform() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["copy", "*"],
+                ["create", ["apply", "order", "_S.orders"]],
+                ["filter", "_S._deleted"]
+            ],
+            "order": [
+                ["add", "_id", ["concat", "order-", "_S.id"]],
+                ["add", "customer", ["parent", "_S._id"]]
+            ]
+        }))
+        .unwrap();
+        let source: Entity = serde_json::from_value(json!({
+            "_id": "1",
+            "_deleted": true,
+            "_ts": 5,
+            "orders": [{"id": "a"}]
+        }))
+        .unwrap();
+        let mut order = Entity::try_from(EntityValue::from(json!({
+            "_id": "order-a",
+            "_deleted": true,
+            "_parent": "1",
+            "customer": "1"
+        })))
+        .unwrap();
+        order.update_hash();
+        let customer = Entity {
+            deleted: true,
+            filtered: true,
+            hash: source.content_hash(),
+            content: source.content.clone(),
+            ..Entity::new("1")
+        };
+        assert_eq!(vec![order, customer], rules.transform(&source).unwrap());
+
+        let rules = RuleSet::parse(&json!({"default": [["add", "_id", 1]]})).unwrap();
+        assert_eq!(
+            "invalid entity {\"_id\":1}: '_id' must be a string",
+            rules.transform(&source).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["comment", "greets every child"],
+                ["add", "children", ["apply", "child", "_S.children"]],
+                ["trace", "_T.children"]
+            ],
+            "child": [
+                ["add", "greeting", ["concat", "hello ", ["trace", "_S.name"]]]
+            ]
+        }))
+        .unwrap();
+        let source = json!({"children": [{"name": "a"}, {"name": "b"}]}).into();
+        let trace = Trace::new();
+        let result = rules.execute_traced(&source, &trace);
+        assert_eq!(rules.execute(&source), result);
+        let event = |pointer: &str, expression: &str, value: serde_json::Value| TraceEvent {
+            pointer: pointer.into(),
+            expression: expression.into(),
+            value: value.into(),
+        };
+        assert_eq!(
+            vec![
+                event("/child/0/2/2", r#"["path","name",["source"]]"#, json!("a")),
+                event("/child/0/2/2", r#"["path","name",["source"]]"#, json!("b")),
+                event(
+                    "/default/2",
+                    r#"["path","children",["target"]]"#,
+                    json!([{"greeting": "hello a"}, {"greeting": "hello b"}])
+                ),
+            ],
+            trace.into_events()
+        );
+    }
+
+    #[test]
+    fn test_conditions() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["add", "type", "_S.type"],
+                ["if", ["list", false, "_S.vip"],
+                    [["add", "vip", true], ["add", "greeting", ["apply", "greeting", "_S"]]],
+                    [["add", "vip", false]]
+                ],
+                ["case",
+                    ["_S.internal", [["add", "kind", "internal"]]],
+                    ["_T.vip", [["add", "kind", "vip"]]],
+                    [true, [["add", "kind", "other"]]]
+                ]
+            ],
+            "greeting": [
+                ["if", "_S.vip", [["add", "text", "welcome"]]]
+            ]
+        }))
+        .unwrap();
+        let vip: EntityValue = json!({"vip": true}).into();
+        assert_eq!(
+            vec![EntityValue::from(json!({
+                "type": null,
+                "vip": true,
+                "greeting": {"text": "welcome"},
+                "kind": "vip"
+            }))],
+            rules.execute(&vip)
+        );
+        let internal: EntityValue = json!({"internal": [true], "type": "a"}).into();
+        assert_eq!(
+            vec![EntityValue::from(
+                json!({"type": "a", "vip": false, "kind": "internal"})
+            )],
+            rules.execute(&internal)
+        );
+        assert_eq!(
+            vec![EntityValue::from(
+                json!({"type": null, "vip": false, "kind": "other"})
+            )],
+            rules.execute(&json!({}).into())
+        );
+    }
+
+    #[test]
+    fn test_copy_and_remove() {
+        let rules = RuleSet::parse(&json!({
+            "default": [
+                ["copy", "*", "_*"],
+                ["remove", "tmp_*"],
+                ["add", "children", ["apply", "child", "_S.children"]]
+            ],
+            "child": [
+                ["copy", ["a", "b"]]
+            ]
+        }))
+        .unwrap();
+        let source = json!({
+            "_id": "1",
+            "_updated": 2,
+            "name": "a",
+            "tmp_x": "x",
+            "children": [{"_id": "2", "a": 1, "b": 2, "c": 3}]
+        })
+        .into();
+        let expected: EntityValue = json!({
+            "_id": "1",
+            "name": "a",
+            "children": [{"_id": "2", "a": 1, "b": 2}]
+        })
+        .into();
+        assert_eq!(vec![expected], rules.execute(&source));
+    }
+
+    #[test]
+    fn test_missing_rules() {
+        assert_eq!(
+            DtlError::new("", "missing 'default' rule"),
+            RuleSet::parse(&json!({"foo": []})).unwrap_err()
+        );
+        assert_eq!(
+            DtlError::new("/default/0/1/1", "unknown rule 'bar'"),
+            RuleSet::parse(&json!({
+                "default": [["create", ["apply", "bar", "_S"]]]
+            }))
+            .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let errors = RuleSet::validate_str(
+            r#"
+            {
+              "type": "dtl",
+              "rules": {
+                "default": [
+                  ["add", "a", ["lower", "_S.a"]],
+                  ["add", "b", ["upper", "_S.b", "_S.c"]],
+                  ["add", "c", ["lowr", "_S.c"]],
+                  ["add", "d", ["concat", ["lower", 1]]],
+                  ["create", ["apply", "foo", "_S.d"]],
+                  ["add", "e"]
+                ]
+              }
+            }
+            "#,
+        );
+        assert_eq!(
+            vec![
+                DtlError::new("/rules/default/4/1/1", "unknown rule 'foo'"),
+                DtlError::new("/rules/default/1/2", "'upper' expects 1 argument(s), got 2"),
+                DtlError::new("/rules/default/2/2/0", "unknown function 'lowr'"),
+                DtlError::new("/rules/default/3/2/1/1", "'lower' expects a string"),
+                DtlError::new("/rules/default/5", "'add' expects 2 argument(s), got 1"),
+            ],
+            errors
+        );
+        assert_eq!(
+            Vec::<DtlError>::new(),
+            RuleSet::validate(&json!({"default": [["add", "a", "_S.a"]]}))
+        );
+    }
+
+    #[test]
+    fn test_recursive_rules() {
+        assert_eq!(
+            DtlError::new(
+                "/default/0/2/1",
+                "rule 'default' is applied recursively (default -> default)"
+            ),
+            RuleSet::parse(&json!({
+                "default": [["add", "a", ["apply", "default", "_S"]]]
+            }))
+            .unwrap_err()
+        );
+        let error = RuleSet::parse(&json!({
+            "default": [["add", "a", ["apply", "foo", "_S"]]],
+            "foo": [["add", "b", ["apply", "bar", "_S"]]],
+            "bar": [["add", "c", ["apply", "foo", "_S"]]]
+        }))
+        .unwrap_err();
+        assert!(error.message.contains("applied recursively"), "{}", error);
+    }
+}
@@ -0,0 +1,757 @@
+use std::fmt;
+
+use crate::{
+    ast::{Expression, Function, Property, Rule, Transform, Variable},
+    entity::EntityValue,
+    parser::{pointer_child, DtlError},
+    rules::RuleSet,
+};
+
+/// The variants of `EntityValue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Null,
+    Bool,
+    Number,
+    String,
+    URI,
+    Date,
+    DateTime,
+    UUID,
+    Bytes,
+    NI,
+    Decimal,
+    List,
+    Object,
+}
+
+const KINDS: [Kind; 13] = [
+    Kind::Null,
+    Kind::Bool,
+    Kind::Number,
+    Kind::String,
+    Kind::URI,
+    Kind::Date,
+    Kind::DateTime,
+    Kind::UUID,
+    Kind::Bytes,
+    Kind::NI,
+    Kind::Decimal,
+    Kind::List,
+    Kind::Object,
+];
+
+impl Kind {
+    pub fn of(value: &EntityValue) -> Kind {
+        match value {
+            EntityValue::Null => Kind::Null,
+            EntityValue::Bool(_) => Kind::Bool,
+            EntityValue::Number(_) => Kind::Number,
+            EntityValue::String(_) => Kind::String,
+            EntityValue::URI(_) => Kind::URI,
+            EntityValue::Date(_) => Kind::Date,
+            EntityValue::DateTime(_) => Kind::DateTime,
+            EntityValue::UUID(_) => Kind::UUID,
+            EntityValue::Bytes(_) => Kind::Bytes,
+            EntityValue::NI(_) => Kind::NI,
+            EntityValue::Decimal(_) => Kind::Decimal,
+            EntityValue::Array(_) => Kind::List,
+            EntityValue::Object(_) => Kind::Object,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Null => "null",
+            Kind::Bool => "boolean",
+            Kind::Number => "number",
+            Kind::String => "string",
+            Kind::URI => "uri",
+            Kind::Date => "date",
+            Kind::DateTime => "datetime",
+            Kind::UUID => "uuid",
+            Kind::Bytes => "bytes",
+            Kind::NI => "ni",
+            Kind::Decimal => "decimal",
+            Kind::List => "list",
+            Kind::Object => "object",
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// The set of values an expression can evaluate to, e.g. `string | list<string>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Type {
+    kinds: u16,
+    /// what the items can be if the value can be a list, `None` if they can be anything
+    items: Option<Box<Type>>,
+}
+
+impl Type {
+    /// The type of an expression that never produces a value, e.g. the items of `[]`.
+    pub fn never() -> Type {
+        Type {
+            kinds: 0,
+            items: None,
+        }
+    }
+
+    pub fn any() -> Type {
+        Type {
+            kinds: KINDS.iter().fold(0, |kinds, kind| kinds | kind.bit()),
+            items: None,
+        }
+    }
+
+    pub fn kind(kind: Kind) -> Type {
+        Type {
+            kinds: kind.bit(),
+            items: None,
+        }
+    }
+
+    pub fn list(items: Type) -> Type {
+        Type {
+            kinds: Kind::List.bit(),
+            items: Some(Box::new(items)),
+        }
+    }
+
+    /// The type of a literal.
+    pub fn of(value: &EntityValue) -> Type {
+        match value {
+            EntityValue::Array(items) => Type::list(
+                items
+                    .iter()
+                    .fold(Type::never(), |items, item| items.union(&Type::of(item))),
+            ),
+            value => Type::kind(Kind::of(value)),
+        }
+    }
+
+    pub fn union(&self, other: &Type) -> Type {
+        let items = match (self.can_be(Kind::List), other.can_be(Kind::List)) {
+            (true, true) => match (&self.items, &other.items) {
+                (Some(a), Some(b)) => Some(Box::new(a.union(b))),
+                _ => None,
+            },
+            (true, false) => self.items.clone(),
+            (false, true) => other.items.clone(),
+            (false, false) => None,
+        };
+        Type {
+            kinds: self.kinds | other.kinds,
+            items,
+        }
+    }
+
+    pub fn can_be(&self, kind: Kind) -> bool {
+        self.kinds & kind.bit() != 0
+    }
+
+    pub fn is_never(&self) -> bool {
+        self.kinds == 0
+    }
+
+    pub fn is_only(&self, kind: Kind) -> bool {
+        self.kinds == kind.bit()
+    }
+
+    /// What the items of the value can be if it is a list.
+    pub fn items(&self) -> Type {
+        match &self.items {
+            _ if !self.can_be(Kind::List) => Type::never(),
+            Some(items) => items.as_ref().clone(),
+            None => Type::any(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Type::any() {
+            return write!(f, "any");
+        }
+        if self.is_never() {
+            return write!(f, "nothing");
+        }
+        let mut kinds = KINDS.iter().filter(|kind| self.can_be(**kind)).peekable();
+        while let Some(kind) = kinds.next() {
+            match (kind, &self.items) {
+                (Kind::List, Some(items)) => write!(f, "list<{}>", items)?,
+                (kind, _) => write!(f, "{}", kind.name())?,
+            }
+            if kinds.peek().is_some() {
+                write!(f, " | ")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Infers the type of an expression, where `current` is the type of `_.`.
+pub fn infer(expression: &Expression, current: &Type) -> Type {
+    Checker::default().expression(expression, "", current)
+}
+
+/// Looks for values that functions would silently drop, e.g. a number passed to `lower`.
+/// The warnings point into the rule set, as parsed by `RuleSet::parse`.
+pub fn check(rules: &RuleSet) -> Vec<DtlError> {
+    let mut rules: Vec<(&str, &Rule)> = rules.iter().collect();
+    rules.sort_by_key(|(name, _)| *name);
+    let mut checker = Checker::default();
+    for (name, rule) in rules {
+        checker.rule(rule, &pointer_child("", name));
+    }
+    checker.warnings
+}
+
+pub fn check_rule(rule: &Rule) -> Vec<DtlError> {
+    let mut checker = Checker::default();
+    checker.rule(rule, "");
+    checker.warnings
+}
+
+#[derive(Default)]
+struct Checker {
+    warnings: Vec<DtlError>,
+}
+
+impl Checker {
+    fn warn(&mut self, pointer: &str, message: String) {
+        self.warnings.push(DtlError::new(pointer, message));
+    }
+
+    fn rule(&mut self, rule: &Rule, pointer: &str) {
+        let current = Type::kind(Kind::Null);
+        for (i, transform) in rule.iter().enumerate() {
+            let pointer = pointer_child(pointer, i);
+            match transform {
+                Transform::Add(property, expression) | Transform::Default(property, expression) => {
+                    if let Property::Expression(property) = property {
+                        let property_pointer = pointer_child(&pointer, 1);
+                        let name = self.expression(property, &property_pointer, &current);
+                        if !name.can_be(Kind::String) {
+                            self.warn(
+                                &property_pointer,
+                                format!("property names must be strings, got {}", name),
+                            );
+                        }
+                    }
+                    self.expression(expression, &pointer_child(&pointer, 2), &current);
+                }
+                Transform::Create(expression, id) => {
+                    let value = self.expression(expression, &pointer_child(&pointer, 1), &current);
+                    if !value.can_be(Kind::Object) && !value.items().can_be(Kind::Object) {
+                        self.warn(
+                            &pointer_child(&pointer, 1),
+                            format!(
+                                "create expects an object or a list of objects, got {}",
+                                value
+                            ),
+                        );
+                    }
+                    if let Some(id) = id {
+                        let entity = Type::kind(Kind::Object);
+                        let id_type = self.expression(id, &pointer_child(&pointer, 2), &entity);
+                        if !id_type.can_be(Kind::String) {
+                            self.warn(
+                                &pointer_child(&pointer, 2),
+                                format!("'_id' must be a string, got {}", id_type),
+                            );
+                        }
+                    }
+                }
+                Transform::Merge(expression) | Transform::MergeUnion(expression) => {
+                    let value_pointer = pointer_child(&pointer, 1);
+                    let value = self.expression(expression, &value_pointer, &current);
+                    if !value.can_be(Kind::Object)
+                        && !value.items().can_be(Kind::Object)
+                        && !is_empty(&value)
+                    {
+                        self.warn(
+                            &value_pointer,
+                            format!(
+                                "merge expects an object or a list of objects, got {}",
+                                value
+                            ),
+                        );
+                    }
+                }
+                Transform::If(condition, then, otherwise) => {
+                    self.expression(condition, &pointer_child(&pointer, 1), &current);
+                    self.rule(then, &pointer_child(&pointer, 2));
+                    self.rule(otherwise, &pointer_child(&pointer, 3));
+                }
+                Transform::Case(branches) => {
+                    for (i, (condition, rule)) in branches.iter().enumerate() {
+                        let branch = pointer_child(&pointer, i + 1);
+                        self.expression(condition, &pointer_child(&branch, 0), &current);
+                        self.rule(rule, &pointer_child(&branch, 1));
+                    }
+                }
+                Transform::Filter(condition) | Transform::Discard(condition) => {
+                    if let Some(condition) = condition {
+                        self.expression(condition, &pointer_child(&pointer, 1), &current);
+                    }
+                }
+                // the traced expression reports its own pointer
+                Transform::Trace(expression) => {
+                    self.expression(expression, &pointer, &current);
+                }
+                Transform::Rename(..)
+                | Transform::Copy(..)
+                | Transform::Remove(..)
+                | Transform::Comment => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression, pointer: &str, current: &Type) -> Type {
+        let arg = |i: usize| pointer_child(pointer, i + 1);
+        match expression {
+            Expression::Literal(value) => Type::of(value),
+            Expression::Variable(Variable::Source) => Type::any(),
+            Expression::Variable(Variable::Target) => Type::kind(Kind::Object),
+            Expression::Variable(Variable::Current) => current.clone(),
+            Expression::Parent(value) | Expression::Root(value) => {
+                self.expression(value, &arg(0), &Type::any())
+            }
+            Expression::Path(segments, value) => {
+                let value_type = self.expression(value, &arg(segments.len()), current);
+                if !segments.is_empty()
+                    && !value_type.can_be(Kind::Object)
+                    && !is_empty(&value_type)
+                {
+                    self.warn(
+                        &arg(segments.len()),
+                        format!("'path' expects an object, got {}", value_type),
+                    );
+                }
+                Type::any()
+            }
+            Expression::Trace(_, value) => self.expression(value, &arg(0), current),
+            Expression::Pattern(_) => Type::kind(Kind::String),
+            Expression::Apply(_, value) => {
+                self.expression(value, &arg(1), current);
+                Type::kind(Kind::Object).union(&Type::list(Type::kind(Kind::Object)))
+            }
+            Expression::Call(Function::Map, args) => {
+                let [function, items] = args.as_slice() else {
+                    return Type::any();
+                };
+                let items = self.expression(items, &arg(1), current);
+                if !items.can_be(Kind::List) {
+                    self.warn(&arg(1), format!("'map' expects a list, got {}", items));
+                }
+                let mapped = Type::list(self.expression(function, &arg(0), &items.items()));
+                if items.is_only(Kind::List) {
+                    mapped
+                } else {
+                    mapped.union(&Type::kind(Kind::Null))
+                }
+            }
+            Expression::Call(function, args) if function.is_coercion() => {
+                let (default, value) = match args.as_slice() {
+                    [value] => (None, value),
+                    [default, value] => (Some(default), value),
+                    _ => return Type::any(),
+                };
+                let value = self.expression(value, &arg(args.len() - 1), current);
+                // the default is evaluated with the values that can't be converted as `_.`
+                let default =
+                    default.map(|default| self.expression(default, &arg(0), &Type::any()));
+                coercion(*function, &value, default.as_ref())
+            }
+            Expression::Call(function, args) => {
+                let types: Vec<Type> = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| self.expression(a, &arg(i), current))
+                    .collect();
+                self.call(*function, &types, pointer)
+            }
+        }
+    }
+
+    /// Checks the argument types of a function and returns the type of its result.
+    fn call(&mut self, function: Function, args: &[Type], pointer: &str) -> Type {
+        let arg = |i: usize| pointer_child(pointer, i + 1);
+        let name = function.name();
+        match function {
+            Function::Concat => {
+                if let [parts] = args {
+                    if !parts.can_be(Kind::String)
+                        && !parts.items().can_be(Kind::String)
+                        && !is_empty(parts)
+                    {
+                        self.warn(
+                            &arg(0),
+                            format!("'{}' expects strings, got {}", name, parts),
+                        );
+                    }
+                } else {
+                    for (i, part) in args.iter().enumerate() {
+                        if !part.can_be(Kind::String) && !is_empty(part) {
+                            self.warn(&arg(i), format!("'{}' expects strings, got {}", name, part));
+                        }
+                    }
+                }
+                Type::kind(Kind::String)
+            }
+            Function::List => Type::list(
+                args.iter()
+                    .fold(Type::never(), |items, arg| items.union(arg)),
+            ),
+            Function::Lower
+            | Function::Upper
+            | Function::Trim
+            | Function::Strip
+            | Function::LStrip
+            | Function::RStrip
+            | Function::RegexSub
+            | Function::Replace
+            | Function::Substring
+            | Function::LJust
+            | Function::RJust => {
+                self.arguments(function, args, pointer);
+                self.strings(name, args, pointer, Kind::String)
+            }
+            Function::StartsWith | Function::EndsWith | Function::Matches => {
+                self.arguments(function, args, pointer);
+                self.strings(name, args, pointer, Kind::Bool)
+            }
+            Function::Split => {
+                self.arguments(function, args, pointer);
+                self.strings(name, args, pointer, Kind::String);
+                Type::list(Type::kind(Kind::String))
+            }
+            // the groups of the pattern decide what is extracted
+            Function::Regex => {
+                self.arguments(function, args, pointer);
+                self.strings(name, args, pointer, Kind::String);
+                Type::any()
+            }
+            Function::Join => {
+                self.arguments(function, args, pointer);
+                Type::kind(Kind::String).union(&Type::kind(Kind::Null))
+            }
+            Function::Length => Type::kind(Kind::Number).union(&Type::kind(Kind::Null)),
+            // values of any kind can be compared, logical operators take any value as a condition
+            Function::And
+            | Function::Eq
+            | Function::Gt
+            | Function::Gte
+            | Function::In
+            | Function::IsEmpty
+            | Function::IsNotEmpty
+            | Function::IsNull
+            | Function::Lt
+            | Function::Lte
+            | Function::Neq
+            | Function::Not
+            | Function::Or => Type::kind(Kind::Bool),
+            Function::Abs
+            | Function::Ceil
+            | Function::Divide
+            | Function::Floor
+            | Function::Max
+            | Function::Min
+            | Function::Minus
+            | Function::Mod
+            | Function::Multiply
+            | Function::Plus
+            | Function::Pow
+            | Function::Round
+            | Function::Sum => self.numbers(function, args, pointer),
+            // handled by expression, as their function or default is evaluated per item
+            Function::Map
+            | Function::Boolean
+            | Function::Decimal
+            | Function::Float
+            | Function::Integer
+            | Function::String => Type::any(),
+        }
+    }
+
+    /// Checks the strings a string function is called on, its last argument, and returns
+    /// the type of calling it on a string or on every string in a list.
+    fn strings(&mut self, name: &str, args: &[Type], pointer: &str, result: Kind) -> Type {
+        let index = args.len().max(1) - 1;
+        let value = args
+            .last()
+            .cloned()
+            .unwrap_or_else(|| Type::kind(Kind::Null));
+        if !value.can_be(Kind::String) && !value.items().can_be(Kind::String) && !is_empty(&value) {
+            self.warn(
+                &pointer_child(pointer, index + 1),
+                format!(
+                    "'{}' expects a string or a list of strings, got {}",
+                    name, value
+                ),
+            );
+        }
+        if value.is_only(Kind::String) {
+            Type::kind(result)
+        } else if value.can_be(Kind::String) {
+            Type::kind(result).union(&Type::list(Type::kind(result)))
+        } else {
+            Type::list(Type::kind(result))
+        }
+    }
+
+    /// Checks the arguments of an arithmetic function, numbers or lists of numbers, and returns
+    /// the type of its result for a number, or for every number in a list.
+    fn numbers(&mut self, function: Function, args: &[Type], pointer: &str) -> Type {
+        let numeric = |t: &Type| t.can_be(Kind::Number) || t.can_be(Kind::Decimal);
+        for (i, arg) in args.iter().enumerate() {
+            if !numeric(arg) && !numeric(&arg.items()) && !is_empty(arg) {
+                self.warn(
+                    &pointer_child(pointer, i + 1),
+                    format!(
+                        "'{}' expects a number or a list of numbers, got {}",
+                        function.name(),
+                        arg
+                    ),
+                );
+            }
+        }
+        // integers that overflow become decimals too, but only decimals going in are tracked
+        let decimal = |t: &Type| t.can_be(Kind::Decimal) || t.items().can_be(Kind::Decimal);
+        let number = if args.iter().any(decimal) {
+            Type::kind(Kind::Number).union(&Type::kind(Kind::Decimal))
+        } else {
+            Type::kind(Kind::Number)
+        };
+        // the digits of round aren't broadcast
+        let values = match function {
+            Function::Sum => return number,
+            Function::Min | Function::Max => return number.union(&Type::kind(Kind::Null)),
+            Function::Round => &args[args.len().max(1) - 1..],
+            _ => args,
+        };
+        if values.iter().any(|value| value.is_only(Kind::List)) {
+            Type::list(number)
+        } else if values.iter().any(|value| value.can_be(Kind::List)) {
+            number
+                .union(&Type::list(number.clone()))
+                .union(&Type::kind(Kind::Null))
+        } else {
+            number.union(&Type::kind(Kind::Null))
+        }
+    }
+
+    /// Checks the arguments before the strings: separators, patterns and fill characters are
+    /// strings, indexes and widths are numbers and whether `regex` finds all matches a boolean.
+    fn arguments(&mut self, function: Function, args: &[Type], pointer: &str) {
+        let Some((_, args)) = args.split_last() else {
+            return;
+        };
+        for (i, arg) in args.iter().enumerate() {
+            let expected = match (function, i) {
+                (Function::Substring, _) | (Function::LJust | Function::RJust, 0) => Kind::Number,
+                (Function::Regex, 1) => Kind::Bool,
+                _ => Kind::String,
+            };
+            if !arg.can_be(expected) && !is_empty(arg) {
+                let expected = Type::kind(expected);
+                self.warn(
+                    &pointer_child(pointer, i + 1),
+                    format!("'{}' expects a {}, got {}", function.name(), expected, arg),
+                );
+            }
+        }
+    }
+}
+
+/// The type of converting a value, or every item in a list. What can't be converted is
+/// replaced by the default, or is null and left out of lists without one.
+fn coercion(function: Function, value: &Type, default: Option<&Type>) -> Type {
+    let kind = match function {
+        Function::Boolean => Kind::Bool,
+        Function::Decimal => Kind::Decimal,
+        Function::String => Kind::String,
+        _ => Kind::Number,
+    };
+    // everything but null converts to a string
+    let always = match function {
+        Function::String => !value.can_be(Kind::Null),
+        _ => value.is_only(kind),
+    };
+    let converted = match default {
+        Some(default) if !always => Type::kind(kind).union(default),
+        _ => Type::kind(kind),
+    };
+    let result = if value.is_only(Kind::List) {
+        Type::list(converted)
+    } else if value.can_be(Kind::List) {
+        converted.union(&Type::list(converted.clone()))
+    } else {
+        converted
+    };
+    if default.is_some() || always || value.is_only(Kind::List) {
+        result
+    } else {
+        result.union(&Type::kind(Kind::Null))
+    }
+}
+
+/// Null and values that are never produced are dropped on purpose, not by mistake.
+fn is_empty(value: &Type) -> bool {
+    value.is_never() || value.is_only(Kind::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, parse_expression};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn infer_json(expression: serde_json::Value) -> String {
+        infer(
+            &parse_expression(&expression).unwrap(),
+            &Type::kind(Kind::Null),
+        )
+        .to_string()
+    }
+
+    #[test]
+    fn test_infer() {
+        assert_eq!("string", infer_json(json!(["concat", "a", "_S.b"])));
+        assert_eq!(
+            "list<number | string>",
+            infer_json(json!(["list", 1, "a", 2]))
+        );
+        assert_eq!("list<nothing>", infer_json(json!(["list"])));
+        assert_eq!(
+            "list<string>",
+            infer_json(json!(["lower", ["list", "A", 1]]))
+        );
+        assert_eq!(
+            "string | list<string>",
+            infer_json(json!(["lower", "_S.a"]))
+        );
+        assert_eq!(
+            "list<string>",
+            infer_json(json!(["map", ["upper", "_."], ["list", "a", "b"]]))
+        );
+        assert_eq!("list<ni>", infer_json(json!(["list", "~:foo:bar"])));
+        assert_eq!(
+            "boolean | list<boolean>",
+            infer_json(json!(["startswith", "a", "_S.a"]))
+        );
+        assert_eq!("list<string>", infer_json(json!(["split", ",", "_S.a"])));
+        assert_eq!("string", infer_json(json!(["strip", "a", ["string", 1]])));
+        assert_eq!("null | number", infer_json(json!(["length", "_S.a"])));
+        assert_eq!(
+            "list<number | string>",
+            infer_json(json!(["integer", "n/a", ["list", "1", "x"]]))
+        );
+        assert_eq!(
+            "null | boolean | list<boolean>",
+            infer_json(json!(["boolean", "_S.a.b"]))
+        );
+        assert_eq!("decimal", infer_json(json!(["decimal", 0, "~f1"])));
+        assert_eq!("null | number", infer_json(json!(["plus", 1, 2])));
+        assert_eq!(
+            "list<number | decimal>",
+            infer_json(json!(["round", 1, ["list", 1.5, "~f1.5"]]))
+        );
+        assert_eq!(
+            "null | number | decimal | list<number | decimal>",
+            infer_json(json!(["divide", "_S.a", 2]))
+        );
+        assert_eq!("number", infer_json(json!(["sum", 1, 2])));
+        assert_eq!(
+            "boolean",
+            infer_json(json!(["and", ["eq", "_S.a", 1], "_S.b"]))
+        );
+        assert_eq!("boolean", infer_json(json!(["is-empty", "_S.a"])));
+        assert_eq!("any", infer_json(json!("_S.a")));
+    }
+
+    #[test]
+    fn test_check() {
+        let rule = parse(&json!([
+            ["add", "a", ["concat", "wor", 1, ["concat", "l", null]]],
+            ["add", "b", ["lower", ["list", 1, 2]]],
+            ["add", "c", ["concat", ["list", "~:foo:bar", "~:foo:baz"]]],
+            ["add", "d", ["map", ["upper", "_."], ["list", 1, null]]],
+            ["add", "e", ["map", ["upper", "_."], ["concat", "a"]]],
+            ["add", "f", ["lower", ["concat", "_S.a", "_S.b"]]],
+            ["add", ["list", "g"], 1],
+            ["merge", ["list", "h"]],
+            ["case", [true, [["add", "i", ["upper", ["list", 1]]]]]],
+            ["create", "j"],
+            ["create", "_S.k", ["list", "_.id"]],
+            ["comment", ["upper", 1]],
+            ["trace", ["lower", ["list", 1]]],
+            [
+                "add",
+                "m",
+                ["rjust", "_S.width", ["list", "-"], ["split", "_S.a", 1.5]]
+            ]
+        ]))
+        .unwrap();
+        assert_eq!(
+            vec![
+                DtlError::new("/0/2/2", "'concat' expects strings, got number"),
+                DtlError::new(
+                    "/1/2/1",
+                    "'lower' expects a string or a list of strings, got list<number>"
+                ),
+                DtlError::new("/2/2/1", "'concat' expects strings, got list<ni>"),
+                DtlError::new(
+                    "/3/2/1/1",
+                    "'upper' expects a string or a list of strings, got null | number"
+                ),
+                DtlError::new("/4/2/2", "'map' expects a list, got string"),
+                DtlError::new("/6/1", "property names must be strings, got list<string>"),
+                DtlError::new(
+                    "/7/1",
+                    "merge expects an object or a list of objects, got list<string>"
+                ),
+                DtlError::new(
+                    "/8/1/1/0/2/1",
+                    "'upper' expects a string or a list of strings, got list<number>"
+                ),
+                DtlError::new(
+                    "/9/1",
+                    "create expects an object or a list of objects, got string"
+                ),
+                DtlError::new("/10/2", "'_id' must be a string, got list<any>"),
+                DtlError::new(
+                    "/12/1/1",
+                    "'lower' expects a string or a list of strings, got list<number>"
+                ),
+                DtlError::new(
+                    "/13/2/3/2",
+                    "'split' expects a string or a list of strings, got number"
+                ),
+                DtlError::new("/13/2/2", "'rjust' expects a string, got list<string>"),
+            ],
+            check_rule(&rule)
+        );
+    }
+
+    #[test]
+    fn test_check_rule_set() {
+        let rules = RuleSet::parse(&json!({
+            "default": [["add", "a", ["apply", "foo", "_S"]]],
+            "foo": [["add", "b", ["concat", "_T"]]]
+        }))
+        .unwrap();
+        assert_eq!(
+            vec![DtlError::new(
+                "/foo/0/2/1",
+                "'concat' expects strings, got object"
+            )],
+            check(&rules)
+        );
+    }
+}